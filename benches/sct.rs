@@ -0,0 +1,81 @@
+//! Benchmarks of [`sct`] for boxes of different sizes
+//!
+//! These don't use the default test harness, so they can be run on stable with
+//! `cargo bench --bench sct`.
+
+use olympian::{sct, SpatialCache};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const NUM_STATIONS: usize = 5000;
+const MIN_RUNS: u32 = 3;
+const MIN_DURATION: Duration = Duration::from_secs(2);
+
+/// stations scattered over about a degree of latitude and longitude, with smooth values and
+/// every 100th an outlier
+fn data() -> SpatialCache {
+    SpatialCache::new(
+        (0..NUM_STATIONS)
+            .map(|i| 60. + ((i as f32).powi(2) * 0.001) % 1.)
+            .collect(),
+        (0..NUM_STATIONS)
+            .map(|i| 10. + ((i as f32 + 1.).powi(2) * 0.001) % 1.)
+            .collect(),
+        (0..NUM_STATIONS).map(|i| (i % 7) as f32 * 50.).collect(),
+        (0..NUM_STATIONS)
+            .map(|i| {
+                if i % 100 == 0 {
+                    10.
+                } else {
+                    (i % 3) as f32 * 0.3
+                }
+            })
+            .collect(),
+    )
+    .unwrap()
+}
+
+fn bench_sct(data: &SpatialCache, num_max: usize) -> Duration {
+    let run = || {
+        sct(
+            black_box(data),
+            5,
+            num_max,
+            // boxes reach far enough to be filled, and are reused within most of that
+            30000.,
+            100000.,
+            1,
+            20,
+            200.,
+            10000.,
+            200.,
+            &[4.; NUM_STATIONS],
+            &[8.; NUM_STATIONS],
+            &[0.5; NUM_STATIONS],
+            None,
+        )
+        .unwrap()
+    };
+
+    let mut runs = 0;
+    let start = Instant::now();
+    while runs < MIN_RUNS || start.elapsed() < MIN_DURATION {
+        black_box(run());
+        runs += 1;
+    }
+
+    start.elapsed() / runs
+}
+
+fn main() {
+    let data = data();
+
+    for num_max in [100, 500, 1000] {
+        println!(
+            "sct, num_max = {num_max:>4}: {:>10.2?} per run",
+            bench_sct(&data, num_max)
+        );
+    }
+}
@@ -5,7 +5,7 @@
 //! and spatial QC tests respectively.
 //!
 //! ```
-//! use olympian::{buddy_check, Flag, SpatialCache};
+//! use olympian::{buddy_check, BuddyCheckOptions, Flag, SpatialCache};
 //!
 //! assert_eq!(
 //!     buddy_check(
@@ -14,7 +14,8 @@
 //!             [60., 60.00011111, 60.00022222].to_vec(),
 //!             [0., 0., 0.].to_vec(),
 //!             [0., 0., 1.].to_vec()
-//!         ),
+//!         )
+//!         .unwrap(),
 //!         &[10000.],
 //!         &[1],
 //!         1.,
@@ -22,7 +23,7 @@
 //!         -0.0065,
 //!         0.01,
 //!         2,
-//!         None,
+//!         BuddyCheckOptions::default(),
 //!     )
 //!     .unwrap(),
 //!     [Flag::Pass, Flag::Pass, Flag::Fail]
@@ -36,13 +37,37 @@ use thiserror::Error;
 
 mod qc_tests;
 pub use qc_tests::{
-    buddy_check::buddy_check, dip_check::dip_check, sct::sct, step_check::step_check,
+    accumulation_check::{accumulation_check, accumulation_to_precipitation},
+    aggregate_check::aggregate_check,
+    buddy_check::{buddy_check, BuddyCheckOptions, Estimator, Neighbourhood, Weighting},
+    change_point_check::{change_point_check, LevelShift},
+    consistency_check::{consistency_check, Relation},
+    dip_check::dip_check,
+    diurnal_check::{diurnal_check, Climatology},
+    radiation_check::radiation_check,
+    sct::sct,
+    snht::snht,
+    spatiotemporal_buddy_check::spatiotemporal_buddy_check,
+    step_check::step_check,
+    variance_check::{variance_check, Variability},
 };
 
 mod util;
+pub use util::batch::batch_check;
+pub use util::failure_tracker::FailureTracker;
+pub use util::interpolation::{qc_filled, Interpolation};
+#[cfg(feature = "parallel")]
+pub use util::parallel::with_num_threads;
+pub use util::solar::solar_elevation;
+pub use util::streaming::StreamingSeries;
+pub use util::Aggregation;
 pub use util::Flag;
+pub use util::Float;
+pub use util::Neighbour;
 pub use util::SeriesCache;
 pub use util::SpatialCache;
+pub use util::SpatioTemporalCache;
+pub use util::Timestamp;
 
 /// Error type for Olympian
 #[derive(Error, Debug, Clone)]
@@ -54,4 +79,10 @@ pub enum Error {
     /// An argument has an invalid value
     #[error("argument {0} does not have a valid value: {1}")]
     InvalidArg(String, String),
+    /// More than one observation was given for the same time
+    #[error("more than one observation was given for timestamp {0:?}")]
+    DuplicateTimestamp(Timestamp),
+    /// An observation's time does not match the period of the series
+    #[error("timestamp {0:?} is not on the series' grid of times")]
+    OffGridTimestamp(Timestamp),
 }
@@ -0,0 +1,270 @@
+use crate::{util, Error, Flag, Float, SeriesCache};
+
+fn check_accumulation<T: Float>(
+    data: &SeriesCache<T>,
+    max_increment: T,
+    jitter: T,
+    max_reset_level: T,
+) -> Result<(Vec<Option<T>>, Vec<Flag>), Error> {
+    if data.num_leading_points < 1
+        || data.num_leading_points as usize + data.num_trailing_points as usize >= data.values.len()
+    {
+        return Err(Error::InvalidInputShape("data".to_string()));
+    }
+    if max_increment < T::zero() {
+        return Err(Error::InvalidArg(
+            "max_increment".to_string(),
+            "must be >= 0".to_string(),
+        ));
+    }
+    if jitter < T::zero() {
+        return Err(Error::InvalidArg(
+            "jitter".to_string(),
+            "must be >= 0".to_string(),
+        ));
+    }
+
+    let end = data.values.len() - data.num_trailing_points as usize;
+    let mut precipitation = Vec::with_capacity(end);
+    let mut flags = Vec::with_capacity(end);
+
+    // the highest accepted level of the gauge since it was last emptied, along with the index of
+    // the last observation at which the gauge was known to be at that level
+    let mut reference: Option<(T, usize)> = data.values[0]
+        .filter(|value| util::is_valid(*value))
+        .map(|value| (value, 0));
+
+    for (i, value) in data.values[..end].iter().enumerate().skip(1) {
+        let curr = match value {
+            Some(curr) if util::is_valid(*curr) => *curr,
+            _ => {
+                flags.push(if value.is_some() {
+                    Flag::Invalid
+                } else {
+                    Flag::DataMissing
+                });
+                precipitation.push(None);
+                continue;
+            }
+        };
+        let Some((level, level_index)) = reference else {
+            // nothing to compare against yet
+            reference = Some((curr, i));
+            flags.push(Flag::DataMissing);
+            precipitation.push(None);
+            continue;
+        };
+        // more than one if the observations in between were missing or flagged
+        let num_periods = i - level_index;
+
+        let (flag, amount) = if curr < level {
+            if level - curr <= jitter {
+                // jitter around a constant level, no precipitation
+                reference = Some((level, i));
+                (Flag::Warn, Some(T::zero()))
+            } else if curr <= max_reset_level {
+                // the gauge was emptied, so we can't know how much fell this period
+                reference = Some((curr, i));
+                (Flag::Pass, None)
+            } else {
+                (Flag::Fail, None)
+            }
+        } else if curr - level > max_increment * T::from_f32(num_periods as f32) {
+            (Flag::Fail, None)
+        } else {
+            reference = Some((curr, i));
+            // if the level at the start of this period isn't known, neither is how much of the
+            // increase fell in it
+            (Flag::Pass, (num_periods == 1).then_some(curr - level))
+        };
+
+        flags.push(flag);
+        precipitation.push(amount);
+    }
+
+    let leading_trim = data.num_leading_points as usize - 1;
+    Ok((
+        precipitation.split_off(leading_trim),
+        flags.split_off(leading_trim),
+    ))
+}
+
+/// Timeseries QC test for the accumulated totals reported by weighing precipitation gauges.
+///
+/// Such gauges report a total that should increase monotonically, until the gauge is emptied and
+/// the total resets. The test keeps track of the reference level of the gauge, i.e. the highest
+/// accepted total since the gauge was last emptied, and flags each observation as follows
+/// - If the observation is missing, or there is no earlier total to compare it against:
+///   DataMissing.
+/// - If the observation is not a valid number: Invalid.
+/// - If the observation is below the reference level by no more than `jitter`: Warn. This is
+///   jitter around a constant level, and is treated as no precipitation.
+/// - If the observation is below the reference level by more than `jitter`, but is no greater
+///   than `max_reset_level`: Pass. This is a plausible reset, and the reference level is reset
+///   with it.
+/// - If the observation is otherwise below the reference level: Fail.
+/// - If the observation is greater than the reference level by more than `max_increment` per
+///   period since the gauge was last known to be at that level: Fail. This allows for the
+///   precipitation that fell while earlier observations were missing or flagged.
+/// - Else: Pass
+///
+/// As a predecessor to each observation is needed, the [`SeriesCache`] provided must have
+/// `num_leading_points` >= 1. Any further leading points are used to establish the reference
+/// level.
+///
+/// To also derive the precipitation in each period, see [`accumulation_to_precipitation`].
+///
+/// ## Input parameters
+///
+/// | Parameter       | Unit | Description |
+/// | --------------- | ---- | ----------- |
+/// | data            | N/A  | See [`SeriesCache`] |
+/// | max_increment   | ou   | The maximum plausible increase in the total over one period |
+/// | jitter          | ou   | The maximum decrease in the total that is treated as jitter |
+/// | max_reset_level | ou   | The maximum total the gauge can report just after being emptied |
+///
+/// ou = Unit of the observation
+///
+/// ## Errors
+///
+/// - data is invalid
+/// - data has `num_leading_points` < 1
+/// - `max_increment` is negative
+/// - `jitter` is negative
+pub fn accumulation_check<T: Float>(
+    data: &SeriesCache<T>,
+    max_increment: T,
+    jitter: T,
+    max_reset_level: T,
+) -> Result<Vec<Flag>, Error> {
+    check_accumulation(data, max_increment, jitter, max_reset_level).map(|(_, flags)| flags)
+}
+
+/// Derive the precipitation in each period from the accumulated totals reported by a weighing
+/// precipitation gauge.
+///
+/// This performs the same QC as [`accumulation_check`], and returns the flags along with the
+/// precipitation over the period leading up to each observation. The precipitation is `None`
+/// where it cannot be determined, i.e. where the observation has Fail, Invalid or DataMissing
+/// flags, where the gauge was emptied during the period, or where the level of the gauge at the
+/// start of the period is unknown as the preceding observation was missing or flagged Fail or
+/// Invalid. Jitter is treated as no precipitation.
+///
+/// ## Errors
+///
+/// See [`accumulation_check`]
+pub fn accumulation_to_precipitation<T: Float>(
+    data: &SeriesCache<T>,
+    max_increment: T,
+    jitter: T,
+    max_reset_level: T,
+) -> Result<(Vec<Option<T>>, Vec<Flag>), Error> {
+    check_accumulation(data, max_increment, jitter, max_reset_level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Timestamp;
+    use chronoutil::RelativeDuration;
+
+    #[test]
+    fn test_accumulation_to_precipitation() {
+        let data = SeriesCache {
+            start_time: Timestamp(0),
+            period: RelativeDuration::hours(1),
+            values: vec![
+                Some(100.),
+                Some(100.),
+                Some(101.5),
+                Some(101.4),
+                Some(101.5),
+                Some(150.),
+                Some(102.),
+                Some(95.),
+                Some(2.),
+                None,
+                Some(3.),
+                Some(3.),
+            ],
+            num_leading_points: 1,
+            num_trailing_points: 1,
+        };
+
+        let (precipitation, flags) = accumulation_to_precipitation(&data, 20., 0.2, 5.).unwrap();
+
+        assert_eq!(
+            flags,
+            [
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Warn,
+                Flag::Pass,
+                Flag::Fail,
+                Flag::Pass,
+                Flag::Fail,
+                Flag::Pass,
+                Flag::DataMissing,
+                Flag::Pass,
+            ]
+        );
+        assert_eq!(
+            precipitation,
+            [
+                Some(0.),
+                Some(1.5),
+                Some(0.),
+                Some(0.),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_accumulation_check_gaps() {
+        let data = SeriesCache {
+            start_time: Timestamp(0),
+            period: RelativeDuration::hours(1),
+            values: vec![
+                Some(10.),
+                None,
+                Some(40.),
+                Some(45.),
+                Some(f32::NAN),
+                Some(50.),
+                Some(80.),
+            ],
+            num_leading_points: 1,
+            num_trailing_points: 0,
+        };
+
+        // increases over several periods are allowed more
+        let (precipitation, flags) = accumulation_to_precipitation(&data, 20., 0.2, 5.).unwrap();
+        assert_eq!(
+            flags,
+            [
+                Flag::DataMissing,
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Invalid,
+                Flag::Pass,
+                Flag::Fail,
+            ]
+        );
+        assert_eq!(precipitation, [None, None, Some(5.), None, None, None]);
+
+        assert!(matches!(
+            accumulation_check(&data, -20., 0.2, 5.),
+            Err(Error::InvalidArg(..))
+        ));
+        assert!(matches!(
+            accumulation_check(&data, 20., -0.2, 5.),
+            Err(Error::InvalidArg(..))
+        ));
+    }
+}
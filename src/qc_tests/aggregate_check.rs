@@ -0,0 +1,176 @@
+use crate::{util, util::Aggregation, Error, Flag, Float, SeriesCache};
+
+/// Timeseries QC test that checks aggregates reported at a low resolution against the
+/// observations they should have been aggregated from, reported at a higher resolution.
+///
+/// Each element of `low_res` is taken to aggregate the period ending at its timestamp. That is,
+/// an element at time `t` is compared against the elements of `high_res` with timestamps in the
+/// interval (`s`, `t`], where `s` is the time of the element before it in `low_res`, aggregated
+/// according to `aggregation`. For example, a 24 hour precipitation total reported at 06:00
+/// should equal the sum of the hourly totals reported from 07:00 the previous day, up to and
+/// including 06:00. Month arithmetic in the periods is respected, so monthly aggregates can be
+/// checked against daily observations, and this is the same convention as used by
+/// [`SeriesCache::resample`].
+///
+/// Each aggregate in the QC window of `low_res` is flagged as follows
+/// - If the aggregate, or any of the observations it covers, are missing: DataMissing. This
+///   includes the case where the interval covered extends outside `high_res`.
+/// - If the absolute difference between the aggregate and the aggregated observations is
+///   greater than `tolerance`: Fail.
+/// - Else: Pass
+///
+/// ## Input parameters
+///
+/// | Parameter   | Unit | Description |
+/// | ----------- | ---- | ----------- |
+/// | high_res    | N/A  | The high resolution series, see [`SeriesCache`]. Leading and trailing points are ignored |
+/// | low_res     | N/A  | The low resolution series to be QCed, see [`SeriesCache`] |
+/// | aggregation | N/A  | How the high resolution observations are aggregated, see [`Aggregation`] |
+/// | tolerance   | ou   | The maximum allowed difference between the aggregate and the aggregated observations |
+///
+/// ou = Unit of the observation
+///
+/// ## Errors
+///
+/// - either series is invalid
+/// - either series' period is not positive
+/// - the timestamps involved are outside the range that can be represented
+pub fn aggregate_check<T: Float>(
+    high_res: &SeriesCache<T>,
+    low_res: &SeriesCache<T>,
+    aggregation: Aggregation,
+    tolerance: T,
+) -> Result<Vec<Flag>, Error> {
+    let start = low_res.num_leading_points as usize;
+    let end = low_res
+        .values
+        .len()
+        .checked_sub(low_res.num_trailing_points as usize)
+        .filter(|end| *end >= start)
+        .ok_or_else(|| Error::InvalidInputShape("low_res".to_string()))?;
+    if low_res.start_time.add_periods(low_res.period, 1)? <= low_res.start_time {
+        return Err(Error::InvalidArg(
+            "low_res.period".to_string(),
+            "must be positive".to_string(),
+        ));
+    }
+
+    (start..end)
+        .map(|i| {
+            let Some(aggregate) = low_res.values[i] else {
+                return Ok(Flag::DataMissing);
+            };
+
+            // taken from the previous element, rather than one period before this one, as they
+            // differ where month arithmetic clamps to the end of the month
+            let interval_start = low_res.time_at(i as i64 - 1)?;
+            let interval_end = low_res.time_at(i as i64)?;
+
+            let first =
+                util::first_index_after(high_res.start_time, high_res.period, interval_start)?;
+            let last = util::first_index_after(high_res.start_time, high_res.period, interval_end)?;
+
+            if first < 0 || last as usize > high_res.values.len() || first >= last {
+                return Ok(Flag::DataMissing);
+            }
+
+            let Some(constituents) = high_res.values[first as usize..last as usize]
+                .iter()
+                .copied()
+                .collect::<Option<Vec<T>>>()
+            else {
+                return Ok(Flag::DataMissing);
+            };
+
+            if (aggregation.apply(&constituents) - aggregate).abs() > tolerance {
+                Ok(Flag::Fail)
+            } else {
+                Ok(Flag::Pass)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Timestamp;
+    use chronoutil::RelativeDuration;
+
+    #[test]
+    fn test_aggregate_check() {
+        // hourly from 01:00 on day 0 to 06:00 on day 2, with 16:00 on day 1 missing
+        let hourly = SeriesCache {
+            start_time: Timestamp(3600),
+            period: RelativeDuration::hours(1),
+            values: (1..=54)
+                .map(|hour| if hour == 40 { None } else { Some(1.) })
+                .collect(),
+            num_leading_points: 0,
+            num_trailing_points: 0,
+        };
+        // daily at 06:00, from day 0 to day 3
+        let daily = SeriesCache {
+            start_time: Timestamp(6 * 3600),
+            period: RelativeDuration::days(1),
+            values: vec![Some(6.), Some(24.), Some(24.), Some(24.)],
+            num_leading_points: 0,
+            num_trailing_points: 0,
+        };
+
+        assert_eq!(
+            aggregate_check(&hourly, &daily, Aggregation::Sum, 0.1).unwrap(),
+            [
+                Flag::DataMissing,
+                Flag::Pass,
+                Flag::DataMissing,
+                Flag::DataMissing
+            ]
+        );
+        assert_eq!(
+            aggregate_check(&hourly, &daily, Aggregation::Max, 0.1).unwrap(),
+            [
+                Flag::DataMissing,
+                Flag::Fail,
+                Flag::DataMissing,
+                Flag::DataMissing
+            ]
+        );
+
+        let backwards = SeriesCache {
+            period: RelativeDuration::days(-1),
+            ..daily
+        };
+        assert!(matches!(
+            aggregate_check(&hourly, &backwards, Aggregation::Sum, 0.1),
+            Err(Error::InvalidArg(name, _)) if name == "low_res.period"
+        ));
+    }
+
+    #[test]
+    fn test_aggregate_check_month_ends() {
+        // daily from the 1st of January 2024
+        let daily = SeriesCache {
+            start_time: Timestamp(1704067200),
+            period: RelativeDuration::days(1),
+            values: vec![Some(1.); 70],
+            num_leading_points: 0,
+            num_trailing_points: 0,
+        };
+        // monthly totals, labelled by the end of the month, i.e. 31 Jan, 29 Feb and 31 Mar
+        let monthly = daily
+            .resample(
+                Timestamp(1706659200),
+                RelativeDuration::months(1),
+                Aggregation::Sum,
+                1,
+            )
+            .unwrap();
+        assert_eq!(monthly.values, [Some(31.), Some(29.), Some(10.)]);
+
+        assert_eq!(
+            aggregate_check(&daily, &monthly, Aggregation::Sum, 0.1).unwrap(),
+            [Flag::Pass, Flag::Pass, Flag::DataMissing]
+        );
+    }
+}
@@ -1,4 +1,196 @@
-use crate::{util, Error, Flag, SpatialCache};
+use crate::{util, Error, Flag, Float, Neighbour, SpatialCache};
+
+/// Statistics used by [`buddy_check`] to summarise the buddies of an observation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Estimator {
+    /// The mean and standard deviation of the buddies
+    MeanStd,
+    /// The median and median absolute deviation (MAD) of the buddies, with the MAD scaled by
+    /// 1.4826 to match the standard deviation of normally distributed values. Unlike the mean and
+    /// standard deviation, these are not dragged towards a bad buddy, so one bad buddy cannot hide
+    /// another.
+    MedianMad,
+}
+
+impl Estimator {
+    /// estimate the centre and spread of a non-empty slice of weighted values, reordering it in
+    /// the process
+    fn estimate<T: Float>(&self, buddies: &mut [(T, T)]) -> (T, T) {
+        match self {
+            Estimator::MeanStd => {
+                let total_weight = buddies.iter().map(|(_, weight)| *weight).sum::<T>();
+                let mean = buddies
+                    .iter()
+                    .map(|(value, weight)| *value * *weight)
+                    .sum::<T>()
+                    / total_weight;
+                // computed from the deviations, rather than as E[x²] - E[x]², to avoid
+                // catastrophic cancellation when the values are large compared to their spread
+                let variance = buddies
+                    .iter()
+                    .map(|(value, weight)| (*value - mean).powi(2) * *weight)
+                    .sum::<T>()
+                    / total_weight;
+
+                (mean, variance.sqrt())
+            }
+            Estimator::MedianMad => {
+                let median = weighted_median(buddies);
+                for (value, _) in buddies.iter_mut() {
+                    *value = (*value - median).abs();
+                }
+
+                (median, T::from_f32(1.4826) * weighted_median(buddies))
+            }
+        }
+    }
+}
+
+/// find the weighted median of a non-empty slice of weighted values, sorting it in the process
+///
+/// With equal weights, this is the ordinary median.
+fn weighted_median<T: Float>(buddies: &mut [(T, T)]) -> T {
+    buddies.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let half_weight = buddies.iter().map(|(_, weight)| *weight).sum::<T>() / T::from_f32(2.);
+    let mut cumulative_weight = T::zero();
+    for (k, (value, weight)) in buddies.iter().enumerate() {
+        cumulative_weight += *weight;
+        if cumulative_weight > half_weight {
+            return *value;
+        }
+        if cumulative_weight == half_weight {
+            // exactly half the weight is at or below this value, so take the midpoint
+            return buddies
+                .get(k + 1)
+                .map_or(*value, |(next, _)| (*value + *next) / T::from_f32(2.));
+        }
+    }
+
+    buddies[buddies.len() - 1].0
+}
+
+/// Selection of the buddies of each observation, used by [`buddy_check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Neighbourhood {
+    /// All observations within the search radius
+    Radius,
+    /// Only the nearest `num_max` observations within the search radius, so that observations in
+    /// dense parts of the network don't get many more buddies than those in sparse parts. The
+    /// radius can be set to [`f32::INFINITY`] to use the nearest observations at any distance.
+    /// `num_max` must be at least as large as all of `nums_min`, or some observations could never
+    /// have enough buddies to be checked.
+    Nearest {
+        /// The maximum number of buddies
+        num_max: usize,
+    },
+}
+
+/// Weighting of buddies by their distance, used by [`buddy_check`]
+///
+/// The distance is scaled by `horizontal_scale` \[m\], and if `vertical_scale` \[m\] is given, the
+/// elevation difference scaled by it is also included, as in [`sct`](crate::sct). The weight of
+/// a buddy at scaled distance r is then `exp(-r²/2)` for `Gaussian`, or `1 / (1 + r)` for
+/// `InverseDistance`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Weighting {
+    /// All buddies are weighted equally
+    Uniform,
+    /// Gaussian weighting, so buddies much further away than `horizontal_scale` have little
+    /// influence
+    Gaussian {
+        /// Horizontal length scale
+        horizontal_scale: f32,
+        /// Vertical length scale
+        vertical_scale: Option<f32>,
+    },
+    /// Inverse distance weighting, with weights halving at `horizontal_scale`
+    InverseDistance {
+        /// Horizontal length scale
+        horizontal_scale: f32,
+        /// Vertical length scale
+        vertical_scale: Option<f32>,
+    },
+}
+
+impl Weighting {
+    fn validate(&self) -> Result<(), Error> {
+        match self {
+            Weighting::Uniform => Ok(()),
+            Weighting::Gaussian {
+                horizontal_scale,
+                vertical_scale,
+            }
+            | Weighting::InverseDistance {
+                horizontal_scale,
+                vertical_scale,
+            } => {
+                if *horizontal_scale > 0. && vertical_scale.is_none_or(|scale| scale > 0.) {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidArg(
+                        "weighting".to_string(),
+                        "length scales must be > 0".to_string(),
+                    ))
+                }
+            }
+        }
+    }
+
+    /// weight of a buddy at `distance` \[m\] with elevation difference `elev_diff` \[m\]
+    fn weight(&self, distance: f32, elev_diff: f32) -> f32 {
+        let scaled_distance = |horizontal_scale: f32, vertical_scale: Option<f32>| {
+            ((distance / horizontal_scale).powi(2)
+                + vertical_scale.map_or(0., |scale| (elev_diff / scale).powi(2)))
+            .sqrt()
+        };
+
+        match self {
+            Weighting::Uniform => 1.,
+            Weighting::Gaussian {
+                horizontal_scale,
+                vertical_scale,
+            } => (-0.5 * scaled_distance(*horizontal_scale, *vertical_scale).powi(2)).exp(),
+            Weighting::InverseDistance {
+                horizontal_scale,
+                vertical_scale,
+            } => 1. / (1. + scaled_distance(*horizontal_scale, *vertical_scale)),
+        }
+    }
+}
+
+/// Optional settings of [`buddy_check`]
+///
+/// The [`Default`] uses all observations within the radius as equally weighted buddies,
+/// summarises them by their mean and standard deviation, and checks every observation. Other
+/// settings can be changed from there, e.g.
+/// `BuddyCheckOptions { estimator: Estimator::MedianMad, ..Default::default() }`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuddyCheckOptions<'a> {
+    /// The selection of buddies within the radius, see [`Neighbourhood`]
+    pub neighbourhood: Neighbourhood,
+    /// The statistics used to summarise the buddies, see [`Estimator`]
+    pub estimator: Estimator,
+    /// The weighting of buddies by distance, see [`Weighting`]
+    pub weighting: Weighting,
+    /// Observations that will be checked. true=check the corresponding observation. Unchecked
+    /// observations will be used to QC others, but will not be QCed themselves
+    pub obs_to_check: Option<&'a [bool]>,
+}
+
+impl Default for BuddyCheckOptions<'_> {
+    fn default() -> Self {
+        Self {
+            neighbourhood: Neighbourhood::Radius,
+            estimator: Estimator::MeanStd,
+            weighting: Weighting::Uniform,
+            obs_to_check: None,
+        }
+    }
+}
 
 /// Spatial QC test that compares an observation against its neighbours (i.e buddies) and flags
 /// outliers.
@@ -11,10 +203,14 @@ use crate::{util, Error, Flag, SpatialCache};
 ///
 /// The buddy check flags observations if the (absolute value of the) difference between the
 /// observations and the average of the neighbours normalized by the standard deviation in the
-/// circle is greater than a predefined `threshold`. If the standard deviation of values in the
-/// neighbourhood is less than `min_std`, then a value of `min_std` is used instead. `min_std`
-/// should be roughly equal to the standard deviation of the error of a typical observation. If it
-/// is too low, then too many observations will be flaged in areas where the variability is low.
+/// circle is greater than a predefined `threshold`. By default, the mean and standard deviation
+/// are used, see [`Estimator::MeanStd`], while with `options.estimator` set to
+/// [`Estimator::MedianMad`] the median and a robust estimate of the standard deviation are used
+/// instead, for when several bad observations may be close together. If the standard deviation
+/// of values in the neighbourhood is less than `min_std`, then a value of `min_std` is used
+/// instead. `min_std` should be roughly equal to the standard deviation of the error of a typical
+/// observation. If it is too low, then too many observations will be flaged in areas where the
+/// variability is low.
 ///
 /// In the case of temperature, elevation differences should be taken into account because all
 /// observations are reported to the elevation of the centroid observation before averaging. A
@@ -23,11 +219,20 @@ use crate::{util, Error, Flag, SpatialCache};
 /// atmosphere). If `max_elev_diff` is negative then elevation difference is not checked and the
 /// observed values are not corrected.
 ///
-/// It is possible to specify an optional vector `obs_to_check` to specify whether an observation
-/// should be checked. The length of `obs_to_check` must be the same as the vector with the values
-/// to check. The buddy check is performed only for values where the corresponding `obs_to_check`
-/// element is set to true, while all values are always used as buddies for checking the data
-/// quality.
+/// By default, all observations within the radius are used as buddies, but with
+/// `options.neighbourhood` they can be limited to the nearest ones, see [`Neighbourhood`].
+/// Likewise, all buddies count equally, but with `options.weighting` closer buddies can be given
+/// more influence on the average and standard deviation, see [`Weighting`].
+///
+/// It is possible to specify an optional vector `options.obs_to_check` to specify whether an
+/// observation should be checked. The length of `obs_to_check` must be the same as the vector
+/// with the values to check. The buddy check is performed only for values where the
+/// corresponding `obs_to_check` element is set to true, while all values are always used as
+/// buddies for checking the data quality.
+///
+/// With the `parallel` feature enabled, observations are checked in parallel, with the same
+/// results as the serial version. The number of threads can be controlled with
+/// `with_num_threads`.
 ///
 /// ## Input parameters
 ///
@@ -39,24 +244,46 @@ use crate::{util, Error, Flag, SpatialCache};
 /// | threshold      | σ    | the variance threshold for flagging a station |
 /// | max_elev_diff  | m    | the maximum difference in elevation for a buddy (if negative will not check for heigh difference) |
 /// | elev_gradient  | ou/m | linear elevation gradient with height |
-/// | min_std        | N/A  | If the standard deviation of values in a neighborhood are less than min_std, min_std will be used instead |
+/// | min_std        | ou   | If the standard deviation of values in a neighborhood are less than min_std, min_std will be used instead |
 /// | num_iterations | N/A  | The number of iterations to perform |
-/// | obs_to_check*  | N/A  | Observations that will be checked. true=check the corresponding observation. Unchecked observations will be used to QC others, but will not be QCed themselves |
+/// | options        | N/A  | Optional settings, see [`BuddyCheckOptions`] |
 ///
-/// \* optional, ou = Unit of the observation, σ = Standard deviations
+/// ou = Unit of the observation, σ = Standard deviations
 #[allow(clippy::too_many_arguments)]
-pub fn buddy_check(
-    data: &SpatialCache,
+pub fn buddy_check<T: Float>(
+    data: &SpatialCache<T>,
     radii: &[f32],
     nums_min: &[u32],
     threshold: f32,
     max_elev_diff: f32,
-    elev_gradient: f32,
-    min_std: f32,
+    elev_gradient: T,
+    min_std: T,
     num_iterations: u32,
-    obs_to_check: Option<&[bool]>,
+    options: BuddyCheckOptions,
 ) -> Result<Vec<Flag>, Error> {
+    let BuddyCheckOptions {
+        neighbourhood,
+        estimator,
+        weighting,
+        obs_to_check,
+    } = options;
+
     // TODO: Check input vectors are properly sized
+    if neighbourhood == (Neighbourhood::Nearest { num_max: 0 }) {
+        return Err(Error::InvalidArg(
+            "neighbourhood".to_string(),
+            "num_max must be > 0".to_string(),
+        ));
+    }
+    if let Neighbourhood::Nearest { num_max } = neighbourhood {
+        if nums_min.iter().any(|num_min| *num_min as usize > num_max) {
+            return Err(Error::InvalidArg(
+                "neighbourhood".to_string(),
+                "num_max must be >= nums_min".to_string(),
+            ));
+        }
+    }
+    weighting.validate()?;
 
     let mut flags: Vec<Flag> = data
         .values
@@ -72,71 +299,119 @@ pub fn buddy_check(
 
     let mut num_removed_last_iteration = 0;
 
-    for _iteration in 1..=num_iterations {
-        for i in 0..data.values.len() {
-            let radius = if radii.len() == 1 { radii[0] } else { radii[i] };
-            let num_min = if nums_min.len() == 1 {
-                nums_min[0]
-            } else {
-                nums_min[i]
-            };
+    // decide whether observation i should fail, given the current flags
+    let check_station = |flags: &[Flag], i: usize| -> Result<(bool, Vec<usize>), Error> {
+        let radius = if radii.len() == 1 { radii[0] } else { radii[i] };
+        let num_min = if nums_min.len() == 1 {
+            nums_min[0]
+        } else {
+            nums_min[i]
+        };
 
-            if flags[i] != Flag::Pass {
-                continue;
+        let (lat, lon, elev) = data.rtree.get_coords_at_index(i);
+        let usable =
+            |neighbour: &Neighbour| neighbour.index != i && flags[neighbour.index] == Flag::Pass;
+        let mut neighbours = match neighbourhood {
+            Neighbourhood::Radius => data.rtree.neighbours_within(lat, lon, radius),
+            Neighbourhood::Nearest { num_max } => {
+                // as flagged observations aren't used as buddies, keep asking for more until
+                // there are num_max that can be. One extra, as the observation itself is among
+                // the nearest
+                let mut num_candidates = num_max + 1;
+                loop {
+                    let candidates =
+                        data.rtree
+                            .nearest_neighbours(lat, lon, num_candidates, radius);
+                    let num_usable = candidates.iter().filter(|n| usable(n)).count();
+                    if num_usable >= num_max || candidates.len() < num_candidates {
+                        break candidates;
+                    }
+                    num_candidates += num_max - num_usable;
+                }
             }
+        };
+        neighbours.retain(|neighbour| neighbour.index != i);
+        if let Neighbourhood::Nearest { num_max } = neighbourhood {
+            // up to and including the num_max-th usable buddy
+            let mut num_usable = 0;
+            if let Some(last) = neighbours.iter().position(|neighbour| {
+                num_usable += usable(neighbour) as usize;
+                num_usable == num_max
+            }) {
+                neighbours.truncate(last + 1);
+            }
+        }
+        // the flags of the neighbours determine which are used as buddies
+        let reads = neighbours.iter().map(|neighbour| neighbour.index).collect();
 
-            if obs_to_check.map_or(true, |inner| inner[i]) {
-                let (lat, lon, elev) = data.rtree.get_coords_at_index(i);
-                let neighbours = data.rtree.get_neighbours(lat, lon, radius, false);
-
-                let mut list_buddies: Vec<f32> = Vec::new();
+        // buddies with their weights
+        let mut list_buddies: Vec<(T, T)> = Vec::new();
 
-                if neighbours.len() >= num_min as usize {
-                    for neighbour in neighbours {
-                        let (_, _, neighbour_elev) = data.rtree.get_coords_at_index(neighbour.data);
+        if neighbours.len() >= num_min as usize {
+            for neighbour in neighbours {
+                let (_, _, neighbour_elev) = data.rtree.get_coords_at_index(neighbour.index);
 
-                        if flags[neighbour.data] != Flag::Pass {
-                            continue;
-                        }
+                if flags[neighbour.index] != Flag::Pass {
+                    continue;
+                }
 
-                        if max_elev_diff > 0.0 {
-                            let elev_diff = elev - neighbour_elev;
+                let elev_diff = elev - neighbour_elev;
+                let weight = T::from_f32(weighting.weight(neighbour.distance, elev_diff));
 
-                            if elev_diff.abs() <= max_elev_diff {
-                                let adjusted_value =
-                                    data.values[neighbour.data] + (elev_diff * elev_gradient);
+                if max_elev_diff > 0.0 {
+                    if elev_diff.abs() <= max_elev_diff {
+                        let adjusted_value =
+                            data.values[neighbour.index] + (T::from_f32(elev_diff) * elev_gradient);
 
-                                list_buddies.push(adjusted_value);
-                            }
-                        } else {
-                            list_buddies.push(data.values[neighbour.data]);
-                        }
+                        list_buddies.push((adjusted_value, weight));
                     }
+                } else {
+                    list_buddies.push((data.values[neighbour.index], weight));
                 }
+            }
+        }
 
-                if list_buddies.len() >= num_min as usize {
-                    let mean: f32 = list_buddies.iter().sum::<f32>() / list_buddies.len() as f32;
-                    let variance: f32 = (list_buddies.iter().map(|x| x.powi(2)).sum::<f32>()
-                        / list_buddies.len() as f32)
-                        - mean.powi(2); // TODO: use a better variance algorithm?
-                                        // let std = variance.sqrt();
-                                        // let std_adjusted = (variance + variance / list_buddies.len() as f32).sqrt();
-                                        // if std_adjusted < min_std {
-                                        //     std_adjusted = min_std
-                                        // }
-                    let std_adjusted = std::cmp::max_by(
-                        (variance + variance / list_buddies.len() as f32).sqrt(),
-                        min_std,
-                        |x, y| x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal),
-                    );
-
-                    if (data.values[i] - mean).abs() / std_adjusted > threshold {
-                        flags[i] = Flag::Fail;
-                    }
-                }
+        let total_weight = list_buddies.iter().map(|(_, weight)| *weight).sum::<T>();
+        if list_buddies.len() >= num_min as usize && total_weight > T::zero() {
+            // the effective number of buddies, which is the number of buddies when they are
+            // weighted equally
+            let n = total_weight.powi(2)
+                / list_buddies
+                    .iter()
+                    .map(|(_, weight)| weight.powi(2))
+                    .sum::<T>();
+            let (expected, std) = estimator.estimate(&mut list_buddies);
+            let std_adjusted =
+                std::cmp::max_by(std * (T::one() + T::one() / n).sqrt(), min_std, |x, y| {
+                    x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal)
+                });
+
+            if (data.values[i] - expected).abs() / std_adjusted > T::from_f32(threshold) {
+                return Ok((true, reads));
             }
         }
 
+        Ok((false, reads))
+    };
+
+    for _iteration in 1..=num_iterations {
+        // stations are checked in parallel with the `parallel` feature, but flagged in order, so
+        // the result is the same as if they were checked serially
+        util::parallel::apply_in_order(
+            data.values.len(),
+            &mut flags,
+            &mut (),
+            |flags, _, i| flags[i] == Flag::Pass && obs_to_check.is_none_or(|inner| inner[i]),
+            // observations only flag themselves, so others are only affected through their buddies
+            |_, _| false,
+            check_station,
+            |flags, _, i, fail| {
+                if fail {
+                    flags[i] = Flag::Fail;
+                }
+            },
+        )?;
+
         let num_removed: u32 = flags
             .iter()
             .fold(0, |acc, flag| acc + (*flag != Flag::Pass) as u32);
@@ -153,32 +428,39 @@ pub fn buddy_check(
 }
 
 #[cfg(test)]
+#[allow(clippy::excessive_precision)]
 mod tests {
     use super::*;
 
     const BUDDY_N: usize = 10;
+
+    fn cache<T: Float>(values: Vec<T>) -> SpatialCache<T> {
+        SpatialCache::new(
+            [60.; BUDDY_N].to_vec(),
+            [
+                60.,
+                60.00011111,
+                60.00022222,
+                60.00033333,
+                60.00044444,
+                60.00055556,
+                60.00066667,
+                60.00077778,
+                60.00088889,
+                60.001,
+            ]
+            .to_vec(),
+            [0.; BUDDY_N].to_vec(),
+            values,
+        )
+        .unwrap()
+    }
+
     #[test]
     fn test_buddy_check() {
         assert_eq!(
             buddy_check(
-                &SpatialCache::new(
-                    [60.; BUDDY_N].to_vec(),
-                    [
-                        60.,
-                        60.00011111,
-                        60.00022222,
-                        60.00033333,
-                        60.00044444,
-                        60.00055556,
-                        60.00066667,
-                        60.00077778,
-                        60.00088889,
-                        60.001,
-                    ]
-                    .to_vec(),
-                    [0.; BUDDY_N].to_vec(),
-                    [0., 0., 0., 0., 0., 0., 0., 0., 0.1, 1.].to_vec()
-                ),
+                &cache(vec![0., 0., 0., 0., 0., 0., 0., 0., 0.1, 1.]),
                 &[10000.],
                 &[1],
                 1.,
@@ -186,7 +468,7 @@ mod tests {
                 -0.0065,
                 0.01,
                 2,
-                None,
+                BuddyCheckOptions::default(),
             )
             .unwrap(),
             [
@@ -201,6 +483,282 @@ mod tests {
                 Flag::Fail,
                 Flag::Fail
             ]
+        );
+
+        // pressure in Pa, where the mean of squares is too large for the spread to survive in f32
+        let mut pressure = vec![100000_f32, 100000.5];
+        pressure = pressure.repeat(BUDDY_N / 2);
+        pressure[9] = 100010.;
+        let flags = buddy_check(
+            &cache(pressure),
+            &[10000.],
+            &[1],
+            3.,
+            200.,
+            0.,
+            0.1,
+            1,
+            BuddyCheckOptions::default(),
         )
+        .unwrap();
+        assert_eq!(flags[..9], [Flag::Pass; 9]);
+        assert_eq!(flags[9], Flag::Fail);
+    }
+
+    #[test]
+    fn test_buddy_check_f64() {
+        // the spread is far below the resolution of f32 at this magnitude
+        let mut values: Vec<f64> = [0., 0.1].repeat(BUDDY_N / 2);
+        values[9] = 2.;
+        let values = values.iter().map(|offset| 1e8 + offset).collect();
+
+        let flags = buddy_check(
+            &cache(values),
+            &[10000.],
+            &[1],
+            3.,
+            200.,
+            0.,
+            0.01,
+            1,
+            BuddyCheckOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(flags[..9], [Flag::Pass; 9]);
+        assert_eq!(flags[9], Flag::Fail);
+    }
+
+    #[test]
+    fn test_buddy_check_robust() {
+        // two bad observations next to each other
+        let data = cache(vec![0., 0.1, 0., 0.1, 0., 0.1, 0., 0.1, 10., 10.5]);
+        let run = |estimator| {
+            buddy_check(
+                &data,
+                &[10000.],
+                &[1],
+                3.,
+                200.,
+                -0.0065,
+                0.1,
+                1,
+                BuddyCheckOptions {
+                    estimator,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        // each inflates the standard deviation of the other's buddies enough to hide it
+        assert_eq!(run(Estimator::MeanStd), [Flag::Pass; BUDDY_N]);
+
+        let flags = run(Estimator::MedianMad);
+        assert_eq!(flags[..8], [Flag::Pass; 8]);
+        assert_eq!(flags[8..], [Flag::Fail, Flag::Fail]);
+    }
+
+    #[test]
+    fn test_buddy_check_weighting() {
+        // a smooth neighbourhood next to a noisy one, roughly 6m apart
+        let data = cache(vec![10., -10., 10., -10., 0., 0., 0., 0., 0., 1.]);
+        let run = |weighting| {
+            buddy_check(
+                &data,
+                &[10000.],
+                &[1],
+                2.,
+                200.,
+                0.,
+                0.1,
+                1,
+                BuddyCheckOptions {
+                    weighting,
+                    ..Default::default()
+                },
+            )
+            .unwrap()[9]
+        };
+
+        // the noisy buddies inflate the standard deviation enough to hide the last observation
+        assert_eq!(run(Weighting::Uniform), Flag::Pass);
+        assert_eq!(
+            run(Weighting::Gaussian {
+                horizontal_scale: 10.,
+                vertical_scale: None,
+            }),
+            Flag::Fail
+        );
+
+        assert!(matches!(
+            buddy_check(
+                &data,
+                &[10000.],
+                &[1],
+                2.,
+                200.,
+                0.,
+                0.1,
+                1,
+                BuddyCheckOptions {
+                    weighting: Weighting::Gaussian {
+                        horizontal_scale: 0.,
+                        vertical_scale: None,
+                    },
+                    ..Default::default()
+                },
+            ),
+            Err(Error::InvalidArg(..))
+        ));
+    }
+
+    #[test]
+    fn test_buddy_check_nearest() {
+        // a smooth neighbourhood next to a noisy one, roughly 6m apart
+        let data = cache(vec![10., -10., 10., -10., 0., 0., 0., 0., 0., 1.]);
+        let run = |neighbourhood| {
+            buddy_check(
+                &data,
+                &[10000.],
+                &[1],
+                2.,
+                200.,
+                0.,
+                0.1,
+                1,
+                BuddyCheckOptions {
+                    neighbourhood,
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert_eq!(run(Neighbourhood::Radius).unwrap()[9], Flag::Pass);
+        assert_eq!(
+            run(Neighbourhood::Nearest { num_max: 4 }).unwrap()[9],
+            Flag::Fail
+        );
+        assert!(matches!(
+            run(Neighbourhood::Nearest { num_max: 0 }),
+            Err(Error::InvalidArg(..))
+        ));
+        assert!(matches!(
+            buddy_check(
+                &data,
+                &[10000.],
+                &[5],
+                2.,
+                200.,
+                0.,
+                0.1,
+                1,
+                BuddyCheckOptions {
+                    neighbourhood: Neighbourhood::Nearest { num_max: 4 },
+                    ..Default::default()
+                },
+            ),
+            Err(Error::InvalidArg(..))
+        ));
+
+        // the nearest observation is invalid, so the next nearest are used instead
+        let flags = buddy_check(
+            &cache(vec![10., -10., 10., -10., 0., 0., 0., 0., f64::NAN, 1.]),
+            &[10000.],
+            &[4],
+            2.,
+            200.,
+            0.,
+            0.1,
+            1,
+            BuddyCheckOptions {
+                neighbourhood: Neighbourhood::Nearest { num_max: 4 },
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(flags[8], Flag::Fail);
+        assert_eq!(flags[9], Flag::Fail);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_buddy_check_parallel() {
+        use crate::with_num_threads;
+
+        const N: usize = 500;
+        // scattered stations, with every 50th observation an outlier
+        let data = SpatialCache::new(
+            (0..N)
+                .map(|i| 60. + ((i as f32).powi(2) * 0.001) % 1.)
+                .collect(),
+            (0..N)
+                .map(|i| 10. + ((i as f32 + 1.).powi(2) * 0.001) % 1.)
+                .collect(),
+            (0..N).map(|i| (i % 7) as f32 * 50.).collect(),
+            (0..N)
+                .map(|i| {
+                    if i % 50 == 0 {
+                        10.
+                    } else {
+                        (i % 3) as f32 * 0.3
+                    }
+                })
+                .collect(),
+        )
+        .unwrap();
+
+        let run = || {
+            buddy_check(
+                &data,
+                &[5000.],
+                &[3],
+                2.,
+                200.,
+                -0.0065,
+                0.1,
+                3,
+                BuddyCheckOptions::default(),
+            )
+            .unwrap()
+        };
+        let serial = with_num_threads(1, run).unwrap();
+        assert!(serial.contains(&Flag::Fail));
+        assert_eq!(with_num_threads(4, run).unwrap(), serial);
+    }
+
+    #[test]
+    fn test_buddy_check_projected() {
+        let values = vec![10., -10., 10., -10., 0., 0., 0., 0., 0., 1.];
+        // the same stations as in `cache`, which are about 6.18m apart
+        let projected = SpatialCache::from_projected(
+            vec![6700000.; BUDDY_N],
+            (0..BUDDY_N).map(|i| 500000. + i as f32 * 6.18).collect(),
+            vec![0.; BUDDY_N],
+            values.clone(),
+        )
+        .unwrap();
+        let run = |data: &SpatialCache<f64>| {
+            buddy_check(
+                data,
+                &[10000.],
+                &[1],
+                2.,
+                200.,
+                0.,
+                0.1,
+                1,
+                BuddyCheckOptions {
+                    weighting: Weighting::Gaussian {
+                        horizontal_scale: 10.,
+                        vertical_scale: None,
+                    },
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        assert_eq!(run(&projected), run(&cache(values)));
+        assert_eq!(run(&projected)[9], Flag::Fail);
     }
 }
@@ -0,0 +1,254 @@
+use crate::{util::Timestamp, Error, Flag, Float, SeriesCache};
+
+/// A level shift in a series, as detected by [`change_point_check`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelShift<T = f32> {
+    /// Index into the series' values of the first point after the shift
+    pub index: usize,
+    /// Time of the first point after the shift
+    pub time: Timestamp,
+    /// Estimated size of the shift, i.e. the mean of the segment after the shift minus the mean
+    /// of the segment before it
+    pub magnitude: T,
+}
+
+/// Running sums over a series, allowing the mean and sum of squared deviations of any segment to
+/// be found in constant time
+struct PrefixSums {
+    sums: Vec<f64>,
+    squares: Vec<f64>,
+}
+
+impl PrefixSums {
+    fn new<T: Float>(values: &[T]) -> Self {
+        // offset the values by the first one to limit cancellation in the sum of squares
+        let offset = values.first().map_or(0., |value| value.as_f64());
+
+        let mut sums = Vec::with_capacity(values.len() + 1);
+        let mut squares = Vec::with_capacity(values.len() + 1);
+        sums.push(0.);
+        squares.push(0.);
+        for value in values {
+            let value = value.as_f64() - offset;
+            sums.push(sums.last().unwrap() + value);
+            squares.push(squares.last().unwrap() + value * value);
+        }
+
+        Self { sums, squares }
+    }
+
+    /// (mean, sum of squared deviations from the mean) of values[lo..hi], with offset mean
+    fn stats(&self, lo: usize, hi: usize) -> (f64, f64) {
+        let n = (hi - lo) as f64;
+        let sum = self.sums[hi] - self.sums[lo];
+        let squares = self.squares[hi] - self.squares[lo];
+
+        (sum / n, (squares - sum * sum / n).max(0.))
+    }
+}
+
+/// find change points in values[lo..hi] by binary segmentation
+fn segment(
+    sums: &PrefixSums,
+    lo: usize,
+    hi: usize,
+    threshold: f64,
+    min_segment_length: usize,
+    change_points: &mut Vec<usize>,
+) {
+    let n = hi - lo;
+    if n < 2 * min_segment_length {
+        return;
+    }
+
+    // find the split that maximises the two sample t-statistic for a difference in means
+    let mut best: Option<(usize, f64)> = None;
+    for split in (lo + min_segment_length)..=(hi - min_segment_length) {
+        let (mean_before, ss_before) = sums.stats(lo, split);
+        let (mean_after, ss_after) = sums.stats(split, hi);
+
+        let n_before = (split - lo) as f64;
+        let n_after = (hi - split) as f64;
+        let pooled_std = ((ss_before + ss_after) / (n as f64 - 2.).max(1.)).sqrt();
+        let statistic =
+            (mean_after - mean_before).abs() / (pooled_std * (1. / n_before + 1. / n_after).sqrt());
+
+        if !statistic.is_nan() && best.is_none_or(|(_, best_statistic)| statistic > best_statistic)
+        {
+            best = Some((split, statistic));
+        }
+    }
+
+    if let Some((split, statistic)) = best {
+        if statistic > threshold {
+            change_points.push(split);
+            segment(
+                sums,
+                lo,
+                split,
+                threshold,
+                min_segment_length,
+                change_points,
+            );
+            segment(
+                sums,
+                split,
+                hi,
+                threshold,
+                min_segment_length,
+                change_points,
+            );
+        }
+    }
+}
+
+/// Timeseries QC test that detects sudden shifts in the level of a series, such as those caused
+/// by sensor replacements or relocations.
+///
+/// Change points are found by binary segmentation: the series is split at the point that
+/// maximises the two sample t-statistic for a difference between the means before and after the
+/// split (equivalent to the maximum of the normalised CUSUM), and if that statistic exceeds
+/// `threshold`, a level shift is recorded and both segments are searched recursively. Segments
+/// shorter than `min_segment_length` valid observations are not considered. Missing values are
+/// skipped, and all points in the series, including leading and trailing points, are used to
+/// detect shifts.
+///
+/// The observations in the QC window are flagged as follows
+/// - If the value is missing: DataMissing.
+/// - If the observation is at or after a detected level shift: Fail.
+/// - Else: Pass
+///
+/// Along with the flags, the level shifts detected are returned in chronological order, with
+/// their magnitudes estimated from the means of the segments either side of them.
+///
+/// ## Input parameters
+///
+/// | Parameter          | Unit | Description |
+/// | ------------------ | ---- | ----------- |
+/// | data               | N/A  | See [`SeriesCache`] |
+/// | threshold          | σ    | The t-statistic above which a level shift is detected |
+/// | min_segment_length | N/A  | The minimum number of valid observations either side of a level shift |
+///
+/// σ = Standard deviations
+///
+/// ## Errors
+///
+/// - data is invalid
+/// - `min_segment_length` is 0
+pub fn change_point_check<T: Float>(
+    data: &SeriesCache<T>,
+    threshold: f32,
+    min_segment_length: usize,
+) -> Result<(Vec<Flag>, Vec<LevelShift<T>>), Error> {
+    let start = data.num_leading_points as usize;
+    let end = data
+        .values
+        .len()
+        .checked_sub(data.num_trailing_points as usize)
+        .filter(|end| *end >= start)
+        .ok_or_else(|| Error::InvalidInputShape("data".to_string()))?;
+    if min_segment_length == 0 {
+        return Err(Error::InvalidArg(
+            "min_segment_length".to_string(),
+            "must be > 0".to_string(),
+        ));
+    }
+
+    let (indices, values): (Vec<usize>, Vec<T>) = data
+        .values
+        .iter()
+        .enumerate()
+        .filter_map(|(i, value)| value.map(|value| (i, value)))
+        .unzip();
+
+    let sums = PrefixSums::new(&values);
+    let mut change_points = Vec::new();
+    segment(
+        &sums,
+        0,
+        values.len(),
+        threshold as f64,
+        min_segment_length,
+        &mut change_points,
+    );
+    change_points.sort_unstable();
+
+    let mut boundaries = Vec::with_capacity(change_points.len() + 2);
+    boundaries.push(0);
+    boundaries.extend_from_slice(&change_points);
+    boundaries.push(values.len());
+
+    let shifts = boundaries
+        .windows(3)
+        .map(|segments| {
+            let (mean_before, _) = sums.stats(segments[0], segments[1]);
+            let (mean_after, _) = sums.stats(segments[1], segments[2]);
+            let index = indices[segments[1]];
+
+            Ok(LevelShift {
+                index,
+                time: data.time_at(index as i64)?,
+                magnitude: T::from_f64(mean_after - mean_before),
+            })
+        })
+        .collect::<Result<Vec<LevelShift<T>>, Error>>()?;
+
+    let flags = (start..end)
+        .map(|i| {
+            if data.values[i].is_none() {
+                Flag::DataMissing
+            } else if shifts.first().is_some_and(|shift| i >= shift.index) {
+                Flag::Fail
+            } else {
+                Flag::Pass
+            }
+        })
+        .collect();
+
+    Ok((flags, shifts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chronoutil::RelativeDuration;
+
+    #[test]
+    fn test_change_point_check() {
+        let noise = [0.1, -0.2, 0.05, 0.15, -0.1, 0., -0.05, 0.2];
+        let mut values: Vec<Option<f32>> = (0..40)
+            .map(|i| Some(10. + noise[i % noise.len()] + if i >= 25 { 3. } else { 0. }))
+            .collect();
+        values[30] = None;
+
+        let data = SeriesCache {
+            start_time: Timestamp(0),
+            period: RelativeDuration::hours(1),
+            values,
+            num_leading_points: 20,
+            num_trailing_points: 10,
+        };
+
+        let (flags, shifts) = change_point_check(&data, 5., 5).unwrap();
+
+        assert_eq!(
+            flags,
+            [
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Fail,
+                Flag::Fail,
+                Flag::Fail,
+                Flag::Fail,
+                Flag::Fail,
+            ]
+        );
+        assert_eq!(shifts.len(), 1);
+        assert_eq!(shifts[0].index, 25);
+        assert_eq!(shifts[0].time, Timestamp(25 * 3600));
+        assert!((shifts[0].magnitude - 3.).abs() < 0.1);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{util, Error, Flag, Float, SeriesCache};
+
+/// Relation between parameters checked by [`consistency_check`]
+///
+/// Each relation is evaluated on the values of all the series at a given time, in the order the
+/// series were passed to [`consistency_check`]. When a relation is violated, only the values
+/// involved in the violation are flagged, as described for each relation.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum Relation<T = f32> {
+    /// The values must be in non-decreasing order, allowing for `tolerance`.
+    ///
+    /// This covers relations such as dew point ≤ air temperature (series passed as `[td, t]`),
+    /// Tmin ≤ T ≤ Tmax (`[tmin, t, tmax]`) and mean wind ≤ gust (`[mean, gust]`). Both values of
+    /// each successive pair that is out of order are flagged, so with `[tmin, t, tmax]`, T > Tmax
+    /// flags T and Tmax, but not Tmin.
+    Ordered {
+        /// Amount a value may exceed its successor by before the relation is violated
+        tolerance: T,
+    },
+    /// The series must be air temperature \[&deg;C\], dew point \[&deg;C\] and relative humidity
+    /// \[%\], in that order.
+    ///
+    /// Relative humidity must be within \[0, 100\] and within `tolerance` percentage points of the
+    /// relative humidity computed from air temperature and dew point using the Magnus formula. If
+    /// it is outside \[0, 100\] only relative humidity is flagged, otherwise all three are.
+    Humidity {
+        /// Allowed difference between observed and computed relative humidity \[%\]
+        tolerance: T,
+    },
+    /// A custom relation, which should return true if the values are consistent. As it can't be
+    /// determined which values are involved, all are flagged when it is violated.
+    Custom(fn(&[T]) -> bool),
+}
+
+/// saturation vapour pressure in hPa over water, using the Magnus formula
+fn saturation_vapour_pressure<T: Float>(temperature: T) -> T {
+    T::from_f32(6.112)
+        * (T::from_f32(17.62) * temperature / (T::from_f32(243.12) + temperature)).exp()
+}
+
+impl<T: Float> Relation<T> {
+    /// set `violated[k]` for each value `values[k]` involved in a violation of the relation
+    fn find_violations(&self, values: &[T], violated: &mut [bool]) {
+        violated.fill(false);
+        match self {
+            Relation::Ordered { tolerance } => {
+                for k in 1..values.len() {
+                    if values[k - 1] > values[k] + *tolerance {
+                        violated[k - 1] = true;
+                        violated[k] = true;
+                    }
+                }
+            }
+            Relation::Humidity { tolerance } => {
+                let (t, td, rh) = (values[0], values[1], values[2]);
+                let hundred = T::from_f32(100.);
+                let rh_computed =
+                    hundred * saturation_vapour_pressure(td) / saturation_vapour_pressure(t);
+
+                if !(T::zero()..=hundred).contains(&rh) {
+                    violated[2] = true;
+                } else if (rh - rh_computed).abs() > *tolerance {
+                    violated.fill(true);
+                }
+            }
+            Relation::Custom(relation) => {
+                if !relation(values) {
+                    violated.fill(true);
+                }
+            }
+        }
+    }
+}
+
+/// Timeseries QC test that checks a relationship between several parameters observed at the same
+/// station.
+///
+/// The series in `data` must be aligned, i.e. have the same `start_time`, `period`, number of
+/// values, and number of leading and trailing points. At each time in the QC window, `relation`
+/// is evaluated on the values of all the series, and the observations are flagged as follows
+/// - If a value is missing in any of the series: DataMissing.
+/// - If the observation is involved in a violation of the relation: Fail.
+/// - Else: Pass
+///
+/// Since it can't be determined which of the parameters involved in a violation is wrong, all of
+/// them are flagged. Which parameters are involved depends on the relation, see [`Relation`].
+///
+/// The returned vector contains the flags for each series in `data`, in the same order.
+///
+/// ## Errors
+///
+/// - fewer than 2 series were provided
+/// - the series are not aligned
+/// - `relation` is [`Relation::Humidity`] and exactly 3 series were not provided
+pub fn consistency_check<T: Float>(
+    data: &[&SeriesCache<T>],
+    relation: Relation<T>,
+) -> Result<Vec<Vec<Flag>>, Error> {
+    if data.len() < 2 {
+        return Err(Error::InvalidInputShape("data".to_string()));
+    }
+    if matches!(relation, Relation::Humidity { .. }) && data.len() != 3 {
+        return Err(Error::InvalidInputShape("data".to_string()));
+    }
+    util::check_aligned(data)?;
+
+    let start = data[0].num_leading_points as usize;
+    let end = data[0].values.len() - data[0].num_trailing_points as usize;
+    if start > end {
+        return Err(Error::InvalidInputShape("data".to_string()));
+    }
+
+    let mut flags = vec![Vec::with_capacity(end - start); data.len()];
+    let mut values = Vec::with_capacity(data.len());
+    let mut violated = vec![false; data.len()];
+
+    for i in start..end {
+        values.clear();
+        values.extend(data.iter().map_while(|series| series.values[i]));
+
+        if values.len() < data.len() {
+            for series_flags in flags.iter_mut() {
+                series_flags.push(Flag::DataMissing);
+            }
+            continue;
+        }
+
+        relation.find_violations(&values, &mut violated);
+        for (series_flags, violated) in flags.iter_mut().zip(&violated) {
+            series_flags.push(if *violated { Flag::Fail } else { Flag::Pass });
+        }
+    }
+
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Timestamp;
+    use chronoutil::RelativeDuration;
+
+    fn series(values: Vec<Option<f32>>) -> SeriesCache {
+        SeriesCache {
+            start_time: Timestamp(0),
+            period: RelativeDuration::hours(1),
+            values,
+            num_leading_points: 0,
+            num_trailing_points: 0,
+        }
+    }
+
+    #[test]
+    fn test_consistency_check() {
+        let tmin = series(vec![Some(1.), Some(1.), Some(1.), None]);
+        let t = series(vec![Some(2.), Some(0.5), Some(5.), Some(2.)]);
+        let tmax = series(vec![Some(3.), Some(3.), Some(3.), Some(3.)]);
+
+        assert_eq!(
+            consistency_check(&[&tmin, &t, &tmax], Relation::Ordered { tolerance: 0.1 }).unwrap(),
+            [
+                [Flag::Pass, Flag::Fail, Flag::Pass, Flag::DataMissing],
+                [Flag::Pass, Flag::Fail, Flag::Fail, Flag::DataMissing],
+                [Flag::Pass, Flag::Pass, Flag::Fail, Flag::DataMissing],
+            ]
+        );
+
+        let rh = series(vec![Some(100.), Some(60.), Some(101.), Some(50.)]);
+        let td = series(vec![Some(2.), Some(-4.), Some(5.), Some(2.)]);
+        assert_eq!(
+            consistency_check(&[&t, &td, &rh], Relation::Humidity { tolerance: 5. }).unwrap(),
+            [
+                [Flag::Pass, Flag::Fail, Flag::Pass, Flag::Fail],
+                [Flag::Pass, Flag::Fail, Flag::Pass, Flag::Fail],
+                [Flag::Pass, Flag::Fail, Flag::Fail, Flag::Fail],
+            ]
+        );
+
+        assert_eq!(
+            consistency_check(
+                &[&tmin, &tmax],
+                Relation::Custom(|values| values[0] > values[1])
+            )
+            .unwrap(),
+            vec![vec![Flag::Fail, Flag::Fail, Flag::Fail, Flag::DataMissing]; 2]
+        );
+
+        let mut misaligned = tmax.clone();
+        misaligned.start_time = Timestamp(3600);
+        assert!(matches!(
+            consistency_check(&[&t, &misaligned], Relation::Ordered { tolerance: 0. }),
+            Err(Error::InvalidArg(_, _))
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Error, Flag, SeriesCache};
+use crate::{Error, Flag, Float, SeriesCache};
 
 /// Timeseries QC test that compares each observation against its immediate predecessor and
 /// successor.
@@ -18,7 +18,7 @@ use crate::{Error, Flag, SeriesCache};
 /// - data is invalid
 /// - data has `num_leading_points` <= 1
 /// - data has `num_trailing_points` <= 1
-pub fn dip_check(data: &SeriesCache, high: f32, max: f32) -> Result<Vec<Flag>, Error> {
+pub fn dip_check<T: Float>(data: &SeriesCache<T>, high: T, max: T) -> Result<Vec<Flag>, Error> {
     let (leading_trim, lead_overflow) = data.num_leading_points.overflowing_sub(1);
     let (trailing_trim, trail_overflow) = data.num_trailing_points.overflowing_sub(1);
 
@@ -39,14 +39,14 @@ pub fn dip_check(data: &SeriesCache, high: f32, max: f32) -> Result<Vec<Flag>, E
             if data.contains(&None) {
                 return Flag::DataMissing;
             }
-            let data: Vec<f32> = data.iter().map(|opt| opt.unwrap()).collect();
+            let data: Vec<T> = data.iter().map(|opt| opt.unwrap()).collect();
 
             if (data[2] < data[1] && data[0] < data[1]) || (data[2] > data[1] && data[0] > data[1])
             {
                 let diffsum = ((data[2] - data[1]).abs() + (data[1] - data[0]).abs()).abs();
                 let diffdiff = ((data[2] - data[1]).abs() - (data[1] - data[0]).abs()).abs();
 
-                if diffdiff < (diffsum * 0.35) {
+                if diffdiff < (diffsum * T::from_f32(0.35)) {
                     if diffsum > max {
                         return Flag::Fail;
                     }
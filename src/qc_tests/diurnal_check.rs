@@ -0,0 +1,171 @@
+use crate::{util, Error, Flag, Float, SeriesCache};
+use chrono::{Datelike, Timelike};
+
+/// Expected diurnal and seasonal cycle used by [`diurnal_check`]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Climatology<T = f32> {
+    /// Expected value for each hour of the day (UTC), for each month of the year, indexed as
+    /// `[month - 1][hour]`
+    HourlyByMonth(Box<[[T; 24]; 12]>),
+    /// Estimate the expected value for each hour of the day (UTC) and month of the year from the
+    /// series itself, as the median of the valid values sharing that hour and month. Hours and
+    /// months with fewer than `min_samples` valid values have no estimate.
+    Estimated {
+        /// Minimum number of valid values needed to estimate the expected value
+        min_samples: usize,
+    },
+}
+
+/// Timeseries QC test that checks the anomaly of each observation from an expected diurnal and
+/// seasonal cycle.
+///
+/// The hour of the day and month of the year of each observation are found from its timestamp,
+/// and the expected value for that hour and month is taken from `climatology`. This allows
+/// thresholds to be set on the deviation from what is normal at that time, rather than on the raw
+/// value, so a value that is ordinary at midday can be flagged at night. Observations will be
+/// flagged as follows:
+/// - If the value is missing: DataMissing.
+/// - If no expected value could be estimated for the observation's hour and month: Inconclusive.
+/// - If the absolute anomaly is greater than `max`: Fail.
+/// - If the absolute anomaly is greater than `high`: Warn.
+/// - Else: Pass
+///
+/// With [`Climatology::Estimated`], the whole series, including leading and trailing points, is
+/// used for the estimate, so it should cover enough time to give a useful number of samples for
+/// each hour and month.
+///
+/// ## Errors
+///
+/// - data is invalid
+/// - `climatology` is [`Climatology::Estimated`] with `min_samples` of 0
+/// - the timestamps of the observations are outside the range that can be represented
+pub fn diurnal_check<T: Float>(
+    data: &SeriesCache<T>,
+    climatology: &Climatology<T>,
+    high: T,
+    max: T,
+) -> Result<Vec<Flag>, Error> {
+    let start = data.num_leading_points as usize;
+    let end = data
+        .values
+        .len()
+        .checked_sub(data.num_trailing_points as usize)
+        .filter(|end| *end >= start)
+        .ok_or_else(|| Error::InvalidInputShape("data".to_string()))?;
+
+    // (month0, hour) of each element of the series
+    let slots = (0..data.values.len())
+        .map(|i| {
+            let datetime = data.time_at(i as i64)?.to_datetime()?;
+            Ok((datetime.month0() as usize, datetime.hour() as usize))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let expected: Box<[[Option<T>; 24]; 12]> = match climatology {
+        Climatology::HourlyByMonth(table) => Box::new(table.map(|month| month.map(Some))),
+        Climatology::Estimated { min_samples } => {
+            if *min_samples == 0 {
+                return Err(Error::InvalidArg(
+                    "min_samples".to_string(),
+                    "must be > 0".to_string(),
+                ));
+            }
+
+            let mut samples: Vec<Vec<T>> = vec![Vec::new(); 12 * 24];
+            for (value, (month, hour)) in data.values.iter().zip(&slots) {
+                if let Some(value) = value {
+                    samples[month * 24 + hour].push(*value);
+                }
+            }
+
+            let mut expected = Box::new([[None; 24]; 12]);
+            for (i, mut samples) in samples.into_iter().enumerate() {
+                if samples.len() >= *min_samples {
+                    expected[i / 24][i % 24] = Some(util::median(&mut samples));
+                }
+            }
+            expected
+        }
+    };
+
+    Ok((start..end)
+        .map(|i| {
+            let Some(value) = data.values[i] else {
+                return Flag::DataMissing;
+            };
+            let (month, hour) = slots[i];
+            let Some(expected) = expected[month][hour] else {
+                return Flag::Inconclusive;
+            };
+
+            let anomaly = (value - expected).abs();
+            if anomaly > max {
+                return Flag::Fail;
+            }
+            if anomaly > high {
+                return Flag::Warn;
+            }
+            Flag::Pass
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Timestamp;
+    use chronoutil::RelativeDuration;
+
+    #[test]
+    fn test_diurnal_check() {
+        // hourly for 10 days from the 1st of January 2024, with a diurnal cycle of 10°C peaking
+        // at midday
+        let cycle = |hour: usize| 10. * (1. - (hour as f32 - 12.).abs() / 12.);
+        let mut values: Vec<Option<f32>> = (0..240).map(|i| Some(cycle(i % 24))).collect();
+        // 12°C at 3am, far above what is normal at night
+        values[123] = Some(12.);
+        values[124] = None;
+
+        let data = SeriesCache {
+            start_time: Timestamp(1704067200),
+            period: RelativeDuration::hours(1),
+            values,
+            num_leading_points: 120,
+            num_trailing_points: 115,
+        };
+
+        assert_eq!(
+            diurnal_check(&data, &Climatology::Estimated { min_samples: 5 }, 3., 5.).unwrap(),
+            [
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Fail,
+                Flag::DataMissing
+            ]
+        );
+
+        let mut table = [[0.; 24]; 12];
+        for (hour, expected) in table[0].iter_mut().enumerate() {
+            *expected = cycle(hour) + 4.;
+        }
+        assert_eq!(
+            diurnal_check(&data, &Climatology::HourlyByMonth(Box::new(table)), 3., 5.).unwrap(),
+            [
+                Flag::Warn,
+                Flag::Warn,
+                Flag::Warn,
+                Flag::Fail,
+                Flag::DataMissing
+            ]
+        );
+
+        assert!(
+            diurnal_check(&data, &Climatology::Estimated { min_samples: 11 }, 3., 5.)
+                .unwrap()
+                .iter()
+                .all(|flag| matches!(flag, Flag::Inconclusive | Flag::DataMissing))
+        );
+    }
+}
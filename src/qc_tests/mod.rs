@@ -1,4 +1,13 @@
+pub(super) mod accumulation_check;
+pub(super) mod aggregate_check;
 pub(super) mod buddy_check;
+pub(super) mod change_point_check;
+pub(super) mod consistency_check;
 pub(super) mod dip_check;
+pub(super) mod diurnal_check;
+pub(super) mod radiation_check;
 pub(super) mod sct;
+pub(super) mod snht;
+pub(super) mod spatiotemporal_buddy_check;
 pub(super) mod step_check;
+pub(super) mod variance_check;
@@ -0,0 +1,128 @@
+use crate::{util::solar, Error, Flag, Float, SeriesCache};
+
+/// Timeseries QC test that checks global radiation against the position of the sun.
+///
+/// The elevation of the sun at the station is computed for the timestamp of each observation, see
+/// [`solar_elevation`](crate::solar_elevation), and from that the extraterrestrial irradiance on
+/// a horizontal surface. Observations will be flagged as follows:
+/// - If the value is missing: DataMissing.
+/// - If the value is greater than both `clear_sky_factor` × the extraterrestrial irradiance and
+///   `max_night_value`: Fail. At night, the extraterrestrial irradiance is 0, so this flags any
+///   value above `max_night_value`.
+/// - Else: Pass
+///
+/// The sun's position is evaluated at the timestamp of each observation, so for observations that
+/// are averages over a period, `clear_sky_factor` and `max_night_value` should be chosen loosely
+/// enough to account for the sun's movement over the period.
+///
+/// ## Input parameters
+///
+/// | Parameter | Unit | Description |
+/// | --------- | ---- | ----------- |
+/// | lat | degrees | Latitude of the station |
+/// | lon | degrees | Longitude of the station, values outside \[-180, 180\] are wrapped into it |
+/// | max_night_value | W/m² | Largest value accepted at night, to allow for sensor offsets |
+/// | clear_sky_factor | | Fraction of the extraterrestrial irradiance that can reach the ground, typically around 1.1 to allow for reflection from clouds |
+///
+/// ## Errors
+///
+/// - data is invalid
+/// - `lat` is not between -90 and 90, or `lon` is not finite
+/// - `clear_sky_factor` is not positive
+/// - the timestamps of the observations are outside the range that can be represented
+pub fn radiation_check<T: Float>(
+    data: &SeriesCache<T>,
+    lat: f32,
+    lon: f32,
+    max_night_value: T,
+    clear_sky_factor: f32,
+) -> Result<Vec<Flag>, Error> {
+    if !(-90. ..=90.).contains(&lat) {
+        return Err(Error::InvalidArg(
+            "lat".to_string(),
+            "must be between -90 and 90".to_string(),
+        ));
+    }
+    if !lon.is_finite() {
+        return Err(Error::InvalidArg(
+            "lon".to_string(),
+            "must be finite".to_string(),
+        ));
+    }
+    if clear_sky_factor <= 0. {
+        return Err(Error::InvalidArg(
+            "clear_sky_factor".to_string(),
+            "must be > 0".to_string(),
+        ));
+    }
+
+    let start = data.num_leading_points as usize;
+    let end = data
+        .values
+        .len()
+        .checked_sub(data.num_trailing_points as usize)
+        .filter(|end| *end >= start)
+        .ok_or_else(|| Error::InvalidInputShape("data".to_string()))?;
+
+    (start..end)
+        .map(|i| {
+            let Some(value) = data.values[i] else {
+                return Ok(Flag::DataMissing);
+            };
+
+            let time = data.time_at(i as i64)?;
+            let elevation = solar::solar_elevation(lat, lon, time)?;
+            let limit = T::from_f32(
+                clear_sky_factor * solar::extraterrestrial_irradiance(elevation, time)?,
+            );
+
+            if value > limit.max(max_night_value) {
+                return Ok(Flag::Fail);
+            }
+            Ok(Flag::Pass)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Timestamp;
+    use chronoutil::RelativeDuration;
+
+    #[test]
+    fn test_radiation_check() {
+        // hourly in Oslo, from midnight UTC on the summer solstice of 2024
+        let mut values = vec![None; 13];
+        // sun below the horizon
+        values[0] = Some(0.);
+        values[1] = Some(30.);
+        // sun near its highest
+        values[10] = Some(700.);
+        values[11] = Some(1200.);
+        let data = SeriesCache {
+            start_time: Timestamp(1718928000),
+            period: RelativeDuration::hours(1),
+            values,
+            num_leading_points: 0,
+            num_trailing_points: 0,
+        };
+
+        let flags = radiation_check(&data, 59.91, 10.75, 5., 1.1).unwrap();
+        assert_eq!(flags[..2], [Flag::Pass, Flag::Fail]);
+        assert_eq!(flags[2..10], [Flag::DataMissing; 8]);
+        assert_eq!(flags[10..], [Flag::Pass, Flag::Fail, Flag::DataMissing]);
+        assert!(radiation_check(&data, 91., 10.75, 5., 1.1).is_err());
+
+        // the same longitude, a turn either way
+        assert_eq!(
+            radiation_check(&data, 59.91, 370.75, 5., 1.1).unwrap(),
+            flags
+        );
+        assert_eq!(
+            radiation_check(&data, 59.91, -349.25, 5., 1.1).unwrap(),
+            flags
+        );
+        assert!(radiation_check(&data, 59.91, f32::NAN, 5., 1.1).is_err());
+    }
+}
@@ -1,5 +1,8 @@
-use crate::{util, util::spatial_tree::SpatialPoint, Error, Flag, SpatialCache};
-use faer::{solvers::SolverCore, Mat};
+use crate::{util, Error, Flag, Float, SpatialCache};
+use faer::{
+    get_global_parallelism, linalg::triangular_inverse::invert_lower_triangular, solvers::SpSolver,
+    Col, Mat, Side,
+};
 
 fn subset<T: Copy>(array: &[T], indices: &[usize]) -> Vec<T> {
     let new_length = indices.len();
@@ -12,26 +15,28 @@ fn subset<T: Copy>(array: &[T], indices: &[usize]) -> Vec<T> {
     new_array
 }
 
-fn compute_vertical_profile_theil_sen(
+/// the background values given by a vertical profile fitted to the observations, or None if one
+/// cannot be fitted
+fn compute_vertical_profile_theil_sen<T: Float>(
     elevs: &[f32],
-    values: &[f32],
+    values: &[T],
     num_min_prof: usize,
     min_elev_diff: f32,
-) -> Vec<f32> {
+) -> Option<Vec<T>> {
     let n = values.len();
 
     // Starting value guesses
-    let gamma: f32 = -0.0065;
-    let mean_t: f32 = values.iter().sum::<f32>() / n as f32; // should this be f64?
+    let gamma = T::from_f32(-0.0065);
+    let mean_t: T = values.iter().sum::<T>() / T::from_f32(n as f32);
 
     // special case when all observations have the same elevation
     if elevs.iter().min_by(|a, b| a.total_cmp(b)) == elevs.iter().max_by(|a, b| a.total_cmp(b)) {
-        return vec![mean_t; n];
+        return Some(vec![mean_t; n]);
     }
 
     // Check if terrain is too flat
-    let z05 = compute_quantile(0.05, elevs);
-    let z95 = compute_quantile(0.95, elevs);
+    let z05 = compute_quantile(0.05, elevs)?;
+    let z95 = compute_quantile(0.95, elevs)?;
 
     // should we use the basic or more complicated vertical profile?
     let use_basic = n < num_min_prof || (z95 - z05) < min_elev_diff;
@@ -41,43 +46,47 @@ fn compute_vertical_profile_theil_sen(
         gamma
     } else {
         let nm = n * (n - 1) / 2;
-        let mut m: Vec<f32> = Vec::with_capacity(nm);
+        let mut m: Vec<T> = Vec::with_capacity(nm);
         for i in 0..(n - 1) {
             for j in (i + 1)..n {
                 m.push(if (elevs[i] - elevs[j]).abs() < 1. {
-                    0.
+                    T::zero()
                 } else {
-                    (values[i] - values[j]) / (elevs[i] - elevs[j])
+                    (values[i] - values[j]) / T::from_f32(elevs[i] - elevs[j])
                 })
             }
         }
-        compute_quantile(0.5, &m)
+        compute_quantile(0.5, &m)?
     };
-    let q: Vec<f32> = values
+    let q: Vec<T> = values
         .iter()
         .zip(elevs)
-        .map(|(val, elev)| val - m_median * elev)
+        .map(|(val, elev)| *val - m_median * T::from_f32(*elev))
         .collect();
-    let q_median = compute_quantile(0.5, &q);
-
-    elevs
-        .iter()
-        .map(|elev| q_median + m_median * elev)
-        .collect()
+    let q_median = compute_quantile(0.5, &q)?;
+
+    Some(
+        elevs
+            .iter()
+            .map(|elev| q_median + m_median * T::from_f32(*elev))
+            .collect(),
+    )
 }
 
-// TODO: replace assertions with errors or remove them
-fn compute_quantile(quantile: f32, array: &[f32]) -> f32 {
-    let mut new_array: Vec<f32> = array
+/// the given quantile of the valid values in an array, or None if there are none
+fn compute_quantile<T: Float>(quantile: f32, array: &[T]) -> Option<T> {
+    let mut new_array: Vec<T> = array
         .iter()
         .copied()
         .filter(|x| util::is_valid(*x))
         .collect();
-    new_array.sort_by(|a, b| a.total_cmp(b));
+    // NaNs were filtered out above, so the values are totally ordered
+    new_array.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
 
     let n = new_array.len();
-
-    assert!(n > 0);
+    if n == 0 {
+        return None;
+    }
 
     // get the quantile from the sorted array
     let lower_index = (quantile * (n - 1) as f32).floor() as usize;
@@ -89,41 +98,70 @@ fn compute_quantile(quantile: f32, array: &[f32]) -> f32 {
     let exact_q = if lower_index == upper_index {
         lower_value
     } else {
-        assert!(upper_quantile > lower_quantile);
-        assert!(quantile >= lower_quantile);
         let f = (quantile - lower_quantile) / (upper_quantile - lower_quantile);
-        assert!(f >= 0.);
-        assert!(f <= 1.);
-        lower_value + (upper_value - lower_value) * f
+        lower_value + (upper_value - lower_value) * T::from_f32(f)
     };
 
-    assert!(util::is_valid(exact_q));
-
-    exact_q
+    util::is_valid(exact_q).then_some(exact_q)
 }
 
-fn invert_matrix(input: &Mat<f32>) -> Mat<f32> {
-    let lu = input.partial_piv_lu();
-    lu.inverse()
-}
+/// solve `s x = d` for symmetric positive definite `s`, returning `x` along with the diagonal of
+/// the inverse of `s`, or None if `s` is not positive definite, or too ill-conditioned for the
+/// solution to be trusted
+///
+/// This uses a Cholesky factorisation `s = L Lᵀ`, which is cheaper and more stable than inverting
+/// `s`.
+fn solve_covariance<T: Float>(s: &Mat<T>, d: &[T]) -> Option<(Vec<T>, Vec<T>)> {
+    let n = s.nrows();
+    let cholesky = s.cholesky(Side::Lower).ok()?;
+    let l = cholesky.compute_l();
+
+    // the squared ratio of the smallest to the largest diagonal element of L is a cheap, if
+    // rough, estimate of the conditioning of s
+    let (min_diag, max_diag) = (0..n)
+        .map(|i| l.read(i, i).powi(2))
+        .fold((T::infinity(), T::zero()), |(min, max), diag| {
+            (min.min(diag), max.max(diag))
+        });
+    let min_allowed_diag = max_diag * <T as num_traits::Float>::epsilon() * T::from_f32(n as f32);
+    if !util::is_valid(min_diag) || min_diag <= min_allowed_diag {
+        return None;
+    }
 
-fn remove_flagged<'a>(
-    neighbours: Vec<&'a SpatialPoint>,
-    distances: Vec<f32>,
-    flags: &[Flag],
-) -> (Vec<&'a SpatialPoint>, Vec<f32>) {
-    let vec_length = neighbours.len();
-    let mut neighbours_new = Vec::with_capacity(vec_length);
-    let mut distances_new = Vec::with_capacity(vec_length);
+    let s_inv_d = cholesky.solve(Col::from_fn(n, |i| d[i]));
 
-    for i in 0..vec_length {
-        if flags[neighbours[i].data] == Flag::Pass {
-            neighbours_new.push(neighbours[i]);
-            distances_new.push(distances[i]);
-        }
-    }
+    // the inverse of s is L⁻ᵀ L⁻¹, so its diagonal is the squared norms of the columns of L⁻¹
+    let mut l_inv = Mat::<T>::zeros(n, n);
+    invert_lower_triangular(l_inv.as_mut(), l.as_ref(), get_global_parallelism());
+    let s_inv_diag: Vec<T> = (0..n).map(|j| l_inv.col(j).squared_norm_l2()).collect();
 
-    (neighbours_new, distances_new)
+    let s_inv_d: Vec<T> = (0..n).map(|i| s_inv_d.read(i)).collect();
+    (s_inv_d
+        .iter()
+        .chain(&s_inv_diag)
+        .all(|x| util::is_valid(*x)))
+    .then_some((s_inv_d, s_inv_diag))
+}
+
+/// Cross-validation residuals of an observation in a box
+struct BoxResidual<T> {
+    index: usize,
+    /// distance from the centre of the box
+    dist: f32,
+    cvres: T,
+    ares: T,
+}
+
+/// Result of running SCT on the box of observations around one observation
+enum BoxResult<T> {
+    /// There were too few observations in the box
+    Isolated,
+    /// The observations in the box could not be used to compute an expected value, for example
+    /// because some were at the same position, making the covariance matrix singular
+    Inconclusive,
+    /// The residuals of each observation in the box, along with the estimated observation error
+    /// variance
+    Checked(Vec<BoxResidual<T>>, T),
 }
 
 /// Spatial QC test that compares an observation to an expected value generated from it's
@@ -142,8 +180,8 @@ fn remove_flagged<'a>(
 ///
 /// The test will only be performed if there are at least `num_min` observations inside the outer
 /// circle. Also, to reduce computation time, only the nearest `num_max` observations will be used
-/// in the outer circle, even if there are more available observations. The SCT inverts a matrix
-/// with the same dimensions as the number of available observations, so preventing really
+/// in the outer circle, even if there are more available observations. The SCT factorises a
+/// matrix with the same dimensions as the number of available observations, so preventing really
 /// large matrices in observation dense areas significantly lowers computation times.
 ///
 /// The thresholds for determining if an observation is removed is set by `pos` and `neg`. `pos`
@@ -160,9 +198,18 @@ fn remove_flagged<'a>(
 /// observations in the outer circle. `dzmin` \[m\] sets the minimum elevation range required to
 /// compute a vertical profile.
 ///
+/// Observations with invalid values or elevations are flagged Invalid, and not used to check
+/// others. If an expected value cannot be computed reliably from the observations around an
+/// observation, for example because several of them are at the same position, making the covariance
+/// matrix that needs to be factorised singular, the observation is flagged Inconclusive.
+///
 /// `num_iterations` specifies how many sweeps of all observations will be performed. Observations
 /// removed in earlier iterations will not be used in the calculations in later iterations.
 ///
+/// With the `parallel` feature enabled, boxes are computed in parallel, with the same results as
+/// the serial version. The number of threads can be controlled with
+/// `with_num_threads`.
+///
 ///  ![Image](https://github.com/metno/titanlib/wiki/images/sct.png)
 ///
 /// ## Input parameters
@@ -186,8 +233,8 @@ fn remove_flagged<'a>(
 ///
 /// \* optional, ou = Unit of the observation, σ = Standard deviations
 #[allow(clippy::too_many_arguments)]
-pub fn sct(
-    data: &SpatialCache,
+pub fn sct<T: Float>(
+    data: &SpatialCache<T>,
     num_min: usize,
     num_max: usize,
     inner_radius: f32,
@@ -209,6 +256,9 @@ pub fn sct(
     if data.rtree.tree.size() != vec_length {
         return Err(Error::InvalidInputShape(String::from("tree_points")));
     }
+    if data.rtree.elevs.len() != vec_length {
+        return Err(Error::InvalidInputShape(String::from("elevs")));
+    }
     if pos.len() != vec_length {
         return Err(Error::InvalidInputShape(String::from("pos")));
     }
@@ -293,173 +343,193 @@ pub fn sct(
     }
 
     let mut flags = vec![Flag::Pass; vec_length];
-    let mut prob_gross_error = vec![0.; vec_length];
-
-    for (flag, elev) in flags.iter_mut().zip(data.rtree.elevs.iter()) {
-        if !util::is_valid(*elev) {
+    let mut prob_gross_error = vec![T::zero(); vec_length];
+
+    for ((flag, elev), value) in flags
+        .iter_mut()
+        .zip(data.rtree.elevs.iter())
+        .zip(data.values.iter())
+    {
+        if !util::is_valid(*elev) || !util::is_valid(*value) {
             *flag = Flag::Invalid;
         }
     }
 
-    // would it make more sense for this to be a 1-based index?
-    for _iteration in 0..num_iterations {
-        // resets each loop, for breaking if we don't throw anything new out
-        let mut num_thrown_out: u32 = 0;
-
-        // keep track of which observations have been checked
-        let mut checked = vec![false; vec_length];
-
-        for i in 0..vec_length {
-            if let Some(obs_to_check_inner) = obs_to_check {
-                if !obs_to_check_inner[i] {
-                    checked[i] = true;
-                    continue;
-                }
-            }
-
-            // continue if station is already flagged
-            if flags[i] != Flag::Pass {
-                checked[i] = true;
-                continue;
-            }
-            if checked[i] {
-                continue;
-            }
-
-            let (neighbours_unfiltered, distances_unfiltered) =
-                data.rtree.get_neighbours_with_distance(
-                    data.rtree.lats[i],
-                    data.rtree.lons[i],
-                    outer_radius,
-                    true,
-                );
-            let (mut neighbours, mut distances) =
-                remove_flagged(neighbours_unfiltered, distances_unfiltered, &flags);
-
-            if neighbours.len() > num_max {
-                let mut pairs: Vec<(&SpatialPoint, f32)> =
-                    neighbours.into_iter().zip(distances.into_iter()).collect();
-                pairs.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
-
-                (neighbours, distances) = pairs.into_iter().take(num_max).unzip()
-            }
-            if neighbours.len() < num_min {
-                checked[i] = true;
-                flags[i] = Flag::Isolated;
-                continue;
-            }
+    // run SCT on the box of observations around observation i
+    let compute_box = |flags: &[Flag], i: usize| -> Result<(BoxResult<T>, Vec<usize>), Error> {
+        let neighbours_unfiltered =
+            data.rtree
+                .neighbours_within(data.rtree.lats[i], data.rtree.lons[i], outer_radius);
+        // the flags of these observations determine the box
+        let reads = neighbours_unfiltered
+            .iter()
+            .map(|neighbour| neighbour.index)
+            .collect();
+        // the neighbours are ordered by distance, so this keeps the nearest
+        let (neighbour_indices, distances): (Vec<usize>, Vec<f32>) = neighbours_unfiltered
+            .into_iter()
+            .filter(|neighbour| flags[neighbour.index] == Flag::Pass)
+            .take(num_max)
+            .map(|neighbour| (neighbour.index, neighbour.distance))
+            .unzip();
+
+        if neighbour_indices.len() < num_min {
+            return Ok((BoxResult::Isolated, reads));
+        }
 
-            let box_size = neighbours.len();
-            let neighbour_indices: Vec<usize> =
-                neighbours.into_iter().map(|point| point.data).collect();
-
-            // call SCT on this box of values
-            let lats_box = subset(&data.rtree.lats, &neighbour_indices);
-            let lons_box = subset(&data.rtree.lons, &neighbour_indices);
-            let elevs_box = subset(&data.rtree.elevs, &neighbour_indices);
-            let values_box = subset(data.data(), &neighbour_indices);
-            let eps2_box = subset(eps2, &neighbour_indices);
-
-            // compute the background
-            // TODO: investigate why titanlib allowed negative num_min_prof
-            let vertical_profile = compute_vertical_profile_theil_sen(
-                &elevs_box,
-                &values_box,
-                num_min_prof,
-                min_elev_diff,
-            );
-
-            let disth: Mat<f32> = Mat::from_fn(box_size, box_size, |i, j| {
-                // TODO: remove this unwrap
-                util::calc_distance(lats_box[i], lons_box[i], lats_box[j], lons_box[j]).unwrap()
-            });
-            let distz: Mat<f32> = Mat::from_fn(box_size, box_size, |i, j| {
-                (elevs_box[i] - elevs_box[j]).abs()
-            });
-            // TODO: remove dh, and just reduce straight into dh_mean?
-            let dh: Vec<f32> = (0..box_size)
-                .map(|i| {
-                    let mut dh_vector = Vec::with_capacity(box_size - 1);
-                    for j in 0..box_size {
-                        if i != j {
-                            dh_vector.push(disth.read(i, j));
-                        }
+        let box_size = neighbour_indices.len();
+
+        // call SCT on this box of values
+        let lats_box = subset(&data.rtree.lats, &neighbour_indices);
+        let lons_box = subset(&data.rtree.lons, &neighbour_indices);
+        let elevs_box = subset(&data.rtree.elevs, &neighbour_indices);
+        let values_box = subset(data.data(), &neighbour_indices);
+        let eps2_box = subset(eps2, &neighbour_indices);
+
+        // compute the background
+        // TODO: investigate why titanlib allowed negative num_min_prof
+        let Some(vertical_profile) = compute_vertical_profile_theil_sen(
+            &elevs_box,
+            &values_box,
+            num_min_prof,
+            min_elev_diff,
+        ) else {
+            return Ok((BoxResult::Inconclusive, reads));
+        };
+
+        let disth: Mat<f32> = Mat::from_fn(box_size, box_size, |i, j| {
+            data.rtree
+                .geometry
+                .distance(lats_box[i], lons_box[i], lats_box[j], lons_box[j])
+        });
+        let distz: Mat<f32> = Mat::from_fn(box_size, box_size, |i, j| {
+            (elevs_box[i] - elevs_box[j]).abs()
+        });
+        // TODO: remove dh, and just reduce straight into dh_mean?
+        let Some(dh): Option<Vec<f32>> = (0..box_size)
+            .map(|i| {
+                let mut dh_vector = Vec::with_capacity(box_size - 1);
+                for j in 0..box_size {
+                    if i != j {
+                        dh_vector.push(disth.read(i, j));
                     }
-                    compute_quantile(0.10, &dh_vector)
-                })
-                .collect();
-
-            let dh_mean: f32 =
-                min_horizontal_scale.max(dh.into_iter().sum::<f32>() / box_size as f32);
-
-            let mut s: Mat<f32> = Mat::from_fn(box_size, box_size, |i, j| {
-                let value = (-0.5 * (disth.read(i, j) / dh_mean).powi(2)
-                    - 0.5 * (distz.read(i, j) / vertical_scale).powi(2))
-                .exp();
-                // weight the diagonal?? (0.5 default)
-                if i == j {
-                    value + eps2_box[i]
-                } else {
-                    value
                 }
-            });
+                compute_quantile(0.10, &dh_vector)
+            })
+            .collect()
+        else {
+            return Ok((BoxResult::Inconclusive, reads));
+        };
+
+        let dh_mean: f32 = min_horizontal_scale.max(dh.into_iter().sum::<f32>() / box_size as f32);
+
+        let half = T::from_f32(0.5);
+        let s: Mat<T> = Mat::from_fn(box_size, box_size, |i, j| {
+            let value = (-half * (T::from_f32(disth.read(i, j)) / T::from_f32(dh_mean)).powi(2)
+                - half * (T::from_f32(distz.read(i, j)) / T::from_f32(vertical_scale)).powi(2))
+            .exp();
+            // weight the diagonal?? (0.5 default)
+            if i == j {
+                value + T::from_f32(eps2_box[i])
+            } else {
+                value
+            }
+        });
 
-            // difference between actual temp and temp from vertical profile
-            let d: Vec<f32> = (0..box_size)
-                .map(|i| values_box[i] - vertical_profile[i])
-                .collect();
+        // difference between actual temp and temp from vertical profile
+        let d: Vec<T> = (0..box_size)
+            .map(|i| values_box[i] - vertical_profile[i])
+            .collect();
 
-            /* ---------------------------------------------------
-            Beginning of real SCT
-            ------------------------------------------------------*/
+        /* ---------------------------------------------------
+        Beginning of real SCT
+        ------------------------------------------------------*/
 
-            // TODO: investigate case of uninvertible (singular) matrices
-            let s_inv = invert_matrix(&s);
+        let Some((s_inv_d, s_inv_diag)) = solve_covariance(&s, &d) else {
+            return Ok((BoxResult::Inconclusive, reads));
+        };
 
-            // unweight the diagonal
-            for (i, eps2) in eps2_box.iter().enumerate() {
-                s.write(i, i, s.read(i, i) - eps2)
-            }
+        // the analysis residuals are (s - eps2) s⁻¹ d - d, where s s⁻¹ d = d, leaving this
+        let ares: Vec<T> = (0..box_size)
+            .map(|i| -T::from_f32(eps2_box[i]) * s_inv_d[i])
+            .collect();
 
-            let s_inv_d: Vec<f32> = (0..box_size)
-                .map(|i| (0..box_size).map(|j| s_inv.read(i, j) * d[j]).sum())
-                .collect();
+        let cvres: Vec<T> = (0..box_size).map(|i| -s_inv_d[i] / s_inv_diag[i]).collect();
 
-            let ares_temp: Vec<f32> = (0..box_size)
-                .map(|i| (0..box_size).map(|j| s.read(i, j) * s_inv_d[j]).sum())
-                .collect();
+        let sig2o = T::from_f32(0.01)
+            .max((0..box_size).map(|i| -d[i] * ares[i]).sum::<T>() / T::from_f32(box_size as f32));
 
-            let z_inv: Vec<f32> = (0..box_size).map(|i| 1. / s_inv.read(i, i)).collect();
+        let residuals = (0..box_size)
+            .map(|i| BoxResidual {
+                index: neighbour_indices[i],
+                dist: distances[i],
+                cvres: cvres[i],
+                ares: ares[i],
+            })
+            .collect();
 
-            let ares: Vec<f32> = (0..box_size).map(|i| ares_temp[i] - d[i]).collect();
+        Ok((BoxResult::Checked(residuals, sig2o), reads))
+    };
 
-            let cvres: Vec<f32> = (0..box_size).map(|i| -1. * z_inv[i] * s_inv_d[i]).collect();
+    // would it make more sense for this to be a 1-based index?
+    for _iteration in 0..num_iterations {
+        // resets each loop, for breaking if we don't throw anything new out
+        let mut num_thrown_out: u32 = 0;
 
-            let sig2o = 0.01_f32
-                .max((0..box_size).map(|i| d[i] * -1. * ares[i]).sum::<f32>() / box_size as f32);
+        // keep track of which observations have been checked
+        let mut checked = vec![false; vec_length];
 
-            let curr = i;
-            for i in 0..box_size {
-                let index = neighbour_indices[i];
-                if let Some(obs_to_check_inner) = obs_to_check {
-                    if !obs_to_check_inner[index] {
-                        checked[curr] = true;
-                        continue;
-                    }
+        // boxes are computed in parallel with the `parallel` feature, but applied in order, so the
+        // result is the same as if they were computed serially
+        util::parallel::apply_in_order(
+            vec_length,
+            &mut flags,
+            &mut checked,
+            |flags, checked, i| {
+                // skip stations that are already flagged or checked
+                obs_to_check.is_none_or(|inner| inner[i]) && flags[i] == Flag::Pass && !checked[i]
+            },
+            // a box checks, and may flag, the stations within inner_radius of its centre
+            |i, j| {
+                let (lat_i, lon_i, _) = data.rtree.get_coords_at_index(i);
+                let (lat_j, lon_j, _) = data.rtree.get_coords_at_index(j);
+                data.rtree.geometry.distance(lat_i, lon_i, lat_j, lon_j) <= inner_radius
+            },
+            compute_box,
+            |flags, checked, i, result| match result {
+                BoxResult::Isolated => {
+                    checked[i] = true;
+                    flags[i] = Flag::Isolated;
                 }
-                let dist = distances[i];
-                if dist <= inner_radius {
-                    let pog: f32 = cvres[i] * ares[i] / sig2o;
-                    assert!(util::is_valid(pog));
-                    prob_gross_error[index] = pog.max(prob_gross_error[index]);
-                    if (cvres[i] < 0. && pog > pos[index]) || (cvres[i] >= 0. && pog > neg[index]) {
-                        flags[index] = Flag::Fail;
-                        num_thrown_out += 1;
+                BoxResult::Inconclusive => {
+                    checked[i] = true;
+                    flags[i] = Flag::Inconclusive;
+                }
+                BoxResult::Checked(residuals, sig2o) => {
+                    for residual in residuals {
+                        let index = residual.index;
+                        if obs_to_check.is_some_and(|inner| !inner[index]) {
+                            continue;
+                        }
+                        if residual.dist <= inner_radius {
+                            let pog: T = residual.cvres * residual.ares / sig2o;
+                            checked[index] = true;
+                            if !util::is_valid(pog) {
+                                flags[index] = Flag::Inconclusive;
+                                continue;
+                            }
+                            prob_gross_error[index] = pog.max(prob_gross_error[index]);
+                            if (residual.cvres < T::zero() && pog > T::from_f32(pos[index]))
+                                || (residual.cvres >= T::zero() && pog > T::from_f32(neg[index]))
+                            {
+                                flags[index] = Flag::Fail;
+                                num_thrown_out += 1;
+                            }
+                        }
                     }
-                    checked[index] = true;
                 }
-            }
-        }
+            },
+        )?;
 
         if num_thrown_out == 0 {
             break;
@@ -482,7 +552,8 @@ mod tests {
                     [10., 10.01, 10.02].to_vec(),
                     [0.; 3].to_vec(),
                     [0., 1., 100.].to_vec(),
-                ),
+                )
+                .unwrap(),
                 3,
                 10,
                 10000.,
@@ -511,7 +582,8 @@ mod tests {
                         .collect(),
                     vec![1.; N],
                     vec![1.; N],
-                ),
+                )
+                .unwrap(),
                 5,
                 100,
                 50000.,
@@ -530,4 +602,119 @@ mod tests {
             vec![Flag::Pass; N]
         );
     }
+
+    #[test]
+    fn test_sct_degenerate() {
+        let run = |data: &SpatialCache, eps2: f32| {
+            let n = data.values.len();
+            sct(
+                data,
+                2,
+                10,
+                10000.,
+                10000.,
+                1,
+                0,
+                100.,
+                10000.,
+                200.,
+                &vec![2.; n],
+                &vec![2.; n],
+                &vec![eps2; n],
+                None,
+            )
+        };
+
+        // stations at the same position, with too little observation error for the covariance
+        // matrix to be invertible
+        let data = SpatialCache::new(
+            vec![60.; 4],
+            vec![10., 10., 10., 10.01],
+            vec![0.; 4],
+            vec![0., 0.1, 0.2, 0.],
+        )
+        .unwrap();
+        // once the first two are flagged, the rest of the stations can be checked
+        assert_eq!(
+            run(&data, 1e-10).unwrap(),
+            [
+                Flag::Inconclusive,
+                Flag::Inconclusive,
+                Flag::Pass,
+                Flag::Pass
+            ]
+        );
+
+        let data = SpatialCache::new(
+            vec![60.; 4],
+            vec![10., 10.01, 10.02, 10.03],
+            vec![0., 0., f32::NAN, 0.],
+            vec![0., f32::NAN, 0., 0.],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&data, 0.5).unwrap(),
+            [Flag::Pass, Flag::Invalid, Flag::Invalid, Flag::Pass]
+        );
+
+        // values can be added after construction, without positions to go with them
+        let mut data =
+            SpatialCache::new(vec![60.; 3], vec![10.; 3], vec![0.; 3], vec![0.; 3]).unwrap();
+        data.values.push(0.);
+        assert!(matches!(
+            run(&data, 0.5),
+            Err(Error::InvalidInputShape(name)) if name == "tree_points"
+        ));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_sct_parallel() {
+        use crate::with_num_threads;
+
+        const N: usize = 500;
+        // scattered stations, with every 50th observation an outlier
+        let data = SpatialCache::new(
+            (0..N)
+                .map(|i| 60. + ((i as f32).powi(2) * 0.001) % 1.)
+                .collect(),
+            (0..N)
+                .map(|i| 10. + ((i as f32 + 1.).powi(2) * 0.001) % 1.)
+                .collect(),
+            (0..N).map(|i| (i % 7) as f32 * 50.).collect(),
+            (0..N)
+                .map(|i| {
+                    if i % 50 == 0 {
+                        10.
+                    } else {
+                        (i % 3) as f32 * 0.3
+                    }
+                })
+                .collect(),
+        )
+        .unwrap();
+
+        let run_sct = || {
+            sct(
+                &data,
+                5,
+                20,
+                5000.,
+                15000.,
+                3,
+                20,
+                200.,
+                10000.,
+                200.,
+                &vec![4.; N],
+                &vec![4.; N],
+                &vec![0.5; N],
+                None,
+            )
+            .unwrap()
+        };
+        let serial = with_num_threads(1, run_sct).unwrap();
+        assert!(serial.contains(&Flag::Fail));
+        assert_eq!(with_num_threads(4, run_sct).unwrap(), serial);
+    }
 }
@@ -0,0 +1,281 @@
+use crate::{Error, Flag, Float, LevelShift, SeriesCache, SpatioTemporalCache};
+
+/// mean of the valid values in a series, or None if it has none
+fn mean<T: Float>(values: &[Option<T>]) -> Option<T> {
+    let (sum, count) = values
+        .iter()
+        .flatten()
+        .fold((T::zero(), 0), |(sum, count), value| {
+            (sum + *value, count + 1)
+        });
+
+    (count > 0).then(|| sum / T::from_f32(count as f32))
+}
+
+/// Pearson correlation between the first differences of two series, over the times where both
+/// are available
+fn difference_correlation<T: Float>(a: &[Option<T>], b: &[Option<T>]) -> Option<T> {
+    let pairs: Vec<(T, T)> = a
+        .windows(2)
+        .zip(b.windows(2))
+        .filter_map(|(a, b)| match (a[0], a[1], b[0], b[1]) {
+            (Some(a0), Some(a1), Some(b0), Some(b1)) => Some((a1 - a0, b1 - b0)),
+            _ => None,
+        })
+        .collect();
+
+    if pairs.len() < 2 {
+        return None;
+    }
+
+    let n = T::from_f32(pairs.len() as f32);
+    let mean_a = pairs.iter().map(|(a, _)| a).sum::<T>() / n;
+    let mean_b = pairs.iter().map(|(_, b)| b).sum::<T>() / n;
+
+    let (cov, var_a, var_b) = pairs.iter().fold(
+        (T::zero(), T::zero(), T::zero()),
+        |(cov, var_a, var_b), (a, b)| {
+            let (da, db) = (*a - mean_a, *b - mean_b);
+            (cov + da * db, var_a + da * da, var_b + db * db)
+        },
+    );
+
+    let correlation = cov / (var_a * var_b).sqrt();
+    correlation.is_finite().then_some(correlation)
+}
+
+/// Relative homogeneity test that detects a single breakpoint in each station's series by
+/// comparing it against a reference series built from its neighbours, using the Standard Normal
+/// Homogeneity Test (SNHT) of Alexandersson (1986).
+///
+/// For each station, the neighbours within `radius` \[m\] are found, keeping only the nearest
+/// `num_max`. Each neighbour is weighted by the square of the correlation between the first
+/// differences of its series and the station's series, and neighbours that are not positively
+/// correlated are discarded. If fewer than `num_min` neighbours remain, the station is flagged
+/// Isolated.
+///
+/// The reference series is the weighted mean of the neighbours' anomalies (their values minus
+/// their mean) at each time, and the difference series is the station's anomalies minus the
+/// reference. This is standardised to `z`, and for each possible breakpoint `k` the statistic
+/// `T(k) = k * mean(z[..k])^2 + (n - k) * mean(z[k..])^2` is computed. If the maximum of `T(k)`
+/// exceeds `threshold`, a breakpoint is detected there, and the station is flagged Fail. If there
+/// are fewer than 3 times where both the station and its reference are available, the station is
+/// flagged DataMissing. Otherwise it is flagged Pass.
+///
+/// All points in the series, including leading and trailing points, are used. Critical values for
+/// `threshold` depend on the length of the series, for 95% confidence they range from about 8 for
+/// 20 points, to about 10 for 100 points. Note that a breakpoint in one station's series also
+/// appears, diluted, in the reference series of its neighbours, so a somewhat higher threshold may
+/// be needed, or the test can be rerun excluding stations found to have breakpoints.
+///
+/// Along with a flag for each station, the breakpoint is returned, if one was detected, as a
+/// [`LevelShift`] whose magnitude is the change in the mean of the difference series.
+///
+/// ## Input parameters
+///
+/// | Parameter | Unit | Description |
+/// | --------- | ---- | ----------- |
+/// | data      | N/A  | See [`SpatioTemporalCache`] |
+/// | radius    | m    | Search radius for neighbours |
+/// | num_min   | N/A  | The minimum number of neighbours needed to build a reference series |
+/// | num_max   | N/A  | A cap on the number of neighbours used to build a reference series |
+/// | threshold | N/A  | The value of the test statistic above which a breakpoint is detected |
+///
+/// ## Errors
+///
+/// - `num_min` is 0
+/// - `num_max` < `num_min`
+#[allow(clippy::type_complexity)]
+pub fn snht<T: Float>(
+    data: &SpatioTemporalCache<T>,
+    radius: f32,
+    num_min: usize,
+    num_max: usize,
+    threshold: f32,
+) -> Result<Vec<(Flag, Option<LevelShift<T>>)>, Error> {
+    if num_min < 1 {
+        return Err(Error::InvalidArg(
+            String::from("num_min"),
+            String::from("must be > 0"),
+        ));
+    }
+    if num_max < num_min {
+        return Err(Error::InvalidArg(
+            String::from("num_max"),
+            String::from("must be >= num_min"),
+        ));
+    }
+
+    (0..data.series.len())
+        .map(|i| {
+            let candidate = &data.series[i];
+
+            let (lat, lon, _) = data.rtree.get_coords_at_index(i);
+            // one extra, as the station itself is among the nearest
+            let neighbours = data.rtree.nearest_neighbours(lat, lon, num_max + 1, radius);
+
+            let weighted: Vec<(&SeriesCache<T>, T, T)> = neighbours
+                .into_iter()
+                .filter(|neighbour| neighbour.index != i)
+                .take(num_max)
+                .filter_map(|neighbour| {
+                    let neighbour = &data.series[neighbour.index];
+                    let correlation = difference_correlation(&candidate.values, &neighbour.values)?;
+                    let mean = mean(&neighbour.values)?;
+
+                    (correlation > T::zero()).then_some((neighbour, correlation.powi(2), mean))
+                })
+                .collect();
+
+            if weighted.len() < num_min {
+                return Ok((Flag::Isolated, None));
+            }
+            let Some(candidate_mean) = mean(&candidate.values) else {
+                return Ok((Flag::DataMissing, None));
+            };
+
+            // difference between the candidate's anomalies and the reference series
+            let (indices, differences): (Vec<usize>, Vec<T>) = candidate
+                .values
+                .iter()
+                .enumerate()
+                .filter_map(|(t, value)| {
+                    let (sum, weight_sum) = weighted
+                        .iter()
+                        .filter_map(|(neighbour, weight, mean)| {
+                            neighbour.values[t].map(|value| (*weight * (value - *mean), *weight))
+                        })
+                        .fold(
+                            (T::zero(), T::zero()),
+                            |(sum, weight_sum), (value, weight)| (sum + value, weight_sum + weight),
+                        );
+
+                    match value {
+                        Some(value) if weight_sum > T::zero() => {
+                            Some((t, *value - candidate_mean - sum / weight_sum))
+                        }
+                        _ => None,
+                    }
+                })
+                .unzip();
+
+            let n = differences.len();
+            if n < 3 {
+                return Ok((Flag::DataMissing, None));
+            }
+
+            let difference_mean = differences.iter().sum::<T>() / T::from_f32(n as f32);
+            let difference_std = (differences
+                .iter()
+                .map(|q| (*q - difference_mean).powi(2))
+                .sum::<T>()
+                / T::from_f32(n as f32))
+            .sqrt();
+            if difference_std == T::zero() {
+                return Ok((Flag::Pass, None));
+            }
+
+            let mut best: Option<(usize, T)> = None;
+            let mut sum_before = T::zero();
+            let z: Vec<T> = differences
+                .iter()
+                .map(|q| (*q - difference_mean) / difference_std)
+                .collect();
+            let z_sum = z.iter().sum::<T>();
+            for k in 1..n {
+                let (k_before, k_after) = (T::from_f32(k as f32), T::from_f32((n - k) as f32));
+                sum_before += z[k - 1];
+                let mean_before = sum_before / k_before;
+                let mean_after = (z_sum - sum_before) / k_after;
+                let statistic = k_before * mean_before.powi(2) + k_after * mean_after.powi(2);
+
+                if best.is_none_or(|(_, best_statistic)| statistic > best_statistic) {
+                    best = Some((k, statistic));
+                }
+            }
+
+            match best {
+                Some((k, statistic)) if statistic > T::from_f32(threshold) => {
+                    let mean_before = differences[..k].iter().sum::<T>() / T::from_f32(k as f32);
+                    let mean_after =
+                        differences[k..].iter().sum::<T>() / T::from_f32((n - k) as f32);
+                    let index = indices[k];
+
+                    Ok((
+                        Flag::Fail,
+                        Some(LevelShift {
+                            index,
+                            time: candidate.time_at(index as i64)?,
+                            magnitude: mean_after - mean_before,
+                        }),
+                    ))
+                }
+                _ => Ok((Flag::Pass, None)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Timestamp;
+    use chronoutil::RelativeDuration;
+
+    #[test]
+    fn test_snht() {
+        const N_STATIONS: usize = 15;
+        const N_TIMES: usize = 40;
+
+        // deterministic pseudo-random noise
+        let mut state: u32 = 12345;
+        let mut noise = || {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            ((state >> 16) % 1000) as f32 / 500. - 1.
+        };
+
+        let signal: Vec<f32> = (0..N_TIMES).map(|t| 5. * (t as f32 * 0.3).sin()).collect();
+        let series = (0..N_STATIONS)
+            .map(|station| SeriesCache {
+                start_time: Timestamp(0),
+                period: RelativeDuration::days(1),
+                values: signal
+                    .iter()
+                    .enumerate()
+                    .map(|(t, value)| {
+                        let shift = if station == 0 && t >= 25 { 4. } else { 0. };
+                        Some(value + noise() + shift)
+                    })
+                    .collect(),
+                num_leading_points: 0,
+                num_trailing_points: 0,
+            })
+            .collect();
+
+        let data = SpatioTemporalCache::new(
+            vec![60.; N_STATIONS],
+            (0..N_STATIONS).map(|i| 10. + i as f32 * 0.01).collect(),
+            vec![0.; N_STATIONS],
+            series,
+        )
+        .unwrap();
+
+        let results = snht(&data, 10000., 3, 20, 20.).unwrap();
+
+        let (flag, shift) = results[0];
+        assert_eq!(flag, Flag::Fail);
+        let shift = shift.unwrap();
+        assert_eq!(shift.index, 25);
+        assert!((shift.magnitude - 4.).abs() < 0.5);
+
+        for (flag, shift) in &results[1..] {
+            assert_eq!(*flag, Flag::Pass);
+            assert_eq!(*shift, None);
+        }
+
+        assert_eq!(
+            snht(&data, 10000., 15, 20, 20.).unwrap(),
+            vec![(Flag::Isolated, None); N_STATIONS]
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use crate::{Error, Flag, Float, SpatioTemporalCache};
+
+/// Spatio-temporal QC test that compares each station's series against its neighbours' (i.e
+/// buddies') over a window of time, and flags stations with persistent biases.
+///
+/// A regular [`buddy_check`](crate::buddy_check) looks at a single time slice, so a station that
+/// is consistently somewhat off will never exceed its threshold. This test instead looks for
+/// deviations that persist over the series.
+///
+/// The buddies of a station (at index i) are the other stations within `radii[i]` \[m\], with
+/// an elevation difference of at most `max_elev_diff` meters. At each time in the series, the
+/// station's anomaly is computed as its value minus the mean of its buddies' values at that time,
+/// with the buddies' values adjusted to the station's elevation using `elev_gradient`. Times where
+/// the station's value is missing, or fewer than `nums_min[i]` buddies have values, are skipped.
+/// If `max_elev_diff` is negative then elevation difference is not checked and the observed
+/// values are not corrected.
+///
+/// The station is flagged as follows
+/// - If it has fewer than `nums_min[i]` buddies: Isolated.
+/// - If there are no times where the anomaly could be computed: DataMissing.
+/// - If the anomaly is greater than `threshold`, or the anomaly is less than `-threshold`, for at
+///   least a fraction `min_persistence` of the times where it could be computed: Fail.
+/// - Else: Pass
+///
+/// `num_iterations` specifies how many sweeps of all stations will be performed. Stations that
+/// failed in earlier iterations will not be used as buddies in later iterations. Within an
+/// iteration, the buddies used don't depend on the results of that iteration, so the order of
+/// the stations doesn't matter.
+///
+/// All points in the series, including leading and trailing points, are used.
+///
+/// It is possible to specify an optional vector `obs_to_check` to specify whether a station
+/// should be checked. The length of `obs_to_check` must be the same as the number of stations.
+/// Unchecked stations are still used as buddies.
+///
+/// ## Input parameters
+///
+/// | Parameter       | Unit | Description |
+/// | --------------- | ---- | ----------- |
+/// | data            | N/A  | See [`SpatioTemporalCache`] |
+/// | radii           | m    | Search radius |
+/// | nums_min        | N/A  | The minimum number of buddies a station can have |
+/// | threshold       | ou   | The anomaly beyond which a station is considered to deviate from its buddies |
+/// | min_persistence | N/A  | The fraction of times a station must deviate in the same direction to be flagged |
+/// | max_elev_diff   | m    | the maximum difference in elevation for a buddy (if negative will not check for heigh difference) |
+/// | elev_gradient   | ou/m | linear elevation gradient with height |
+/// | num_iterations  | N/A  | The number of iterations to perform |
+/// | obs_to_check*   | N/A  | Stations that will be checked. true=check the corresponding station. Unchecked stations will be used to QC others, but will not be QCed themselves |
+///
+/// \* optional, ou = Unit of the observation
+///
+/// ## Errors
+///
+/// - `radii`, `nums_min` or `obs_to_check` have the wrong length
+/// - `min_persistence` is not within (0, 1]
+#[allow(clippy::too_many_arguments)]
+pub fn spatiotemporal_buddy_check<T: Float>(
+    data: &SpatioTemporalCache<T>,
+    radii: &[f32],
+    nums_min: &[u32],
+    threshold: T,
+    min_persistence: f32,
+    max_elev_diff: f32,
+    elev_gradient: T,
+    num_iterations: u32,
+    obs_to_check: Option<&[bool]>,
+) -> Result<Vec<Flag>, Error> {
+    let num_stations = data.series.len();
+
+    if radii.len() != 1 && radii.len() != num_stations {
+        return Err(Error::InvalidInputShape(String::from("radii")));
+    }
+    if nums_min.len() != 1 && nums_min.len() != num_stations {
+        return Err(Error::InvalidInputShape(String::from("nums_min")));
+    }
+    if let Some(obs_to_check_inner) = obs_to_check {
+        if obs_to_check_inner.len() != num_stations {
+            return Err(Error::InvalidInputShape(String::from("obs_to_check")));
+        }
+    }
+    if !(min_persistence > 0. && min_persistence <= 1.) {
+        return Err(Error::InvalidArg(
+            String::from("min_persistence"),
+            String::from("must be within (0, 1]"),
+        ));
+    }
+
+    let num_times = data.series.first().map_or(0, |series| series.values.len());
+    let mut flags = vec![Flag::Pass; num_stations];
+
+    for _iteration in 1..=num_iterations {
+        let mut num_removed: u32 = 0;
+        // stations that failed in earlier iterations, which are not used as buddies. Being
+        // isolated or missing data says nothing about a station's values, so those still are
+        let failed: Vec<bool> = flags.iter().map(|flag| *flag == Flag::Fail).collect();
+
+        for i in 0..num_stations {
+            let radius = if radii.len() == 1 { radii[0] } else { radii[i] };
+            let num_min = if nums_min.len() == 1 {
+                nums_min[0]
+            } else {
+                nums_min[i]
+            } as usize;
+
+            if flags[i] != Flag::Pass || !obs_to_check.is_none_or(|inner| inner[i]) {
+                continue;
+            }
+
+            let (lat, lon, elev) = data.rtree.get_coords_at_index(i);
+            let neighbours = data.rtree.neighbours_within(lat, lon, radius);
+
+            // buddies and the adjustment needed to bring their values to the station's elevation
+            let buddies: Vec<(usize, T)> = neighbours
+                .into_iter()
+                .filter(|neighbour| neighbour.index != i && !failed[neighbour.index])
+                .filter_map(|neighbour| {
+                    let (_, _, neighbour_elev) = data.rtree.get_coords_at_index(neighbour.index);
+                    let elev_diff = elev - neighbour_elev;
+
+                    if max_elev_diff < 0. {
+                        Some((neighbour.index, T::zero()))
+                    } else if elev_diff.abs() <= max_elev_diff {
+                        Some((neighbour.index, T::from_f32(elev_diff) * elev_gradient))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            if buddies.len() < num_min {
+                flags[i] = Flag::Isolated;
+                continue;
+            }
+
+            let mut num_valid = 0;
+            let mut num_above = 0;
+            let mut num_below = 0;
+            for t in 0..num_times {
+                let Some(value) = data.series[i].values[t] else {
+                    continue;
+                };
+
+                let (sum, count) = buddies
+                    .iter()
+                    .filter_map(|(j, adjustment)| {
+                        data.series[*j].values[t].map(|v| v + *adjustment)
+                    })
+                    .fold((T::zero(), 0), |(sum, count), v| (sum + v, count + 1));
+                if count < num_min || count == 0 {
+                    continue;
+                }
+
+                let anomaly = value - sum / T::from_f32(count as f32);
+                num_valid += 1;
+                if anomaly > threshold {
+                    num_above += 1;
+                } else if anomaly < -threshold {
+                    num_below += 1;
+                }
+            }
+
+            if num_valid == 0 {
+                flags[i] = Flag::DataMissing;
+            } else if num_above.max(num_below) as f32 >= min_persistence * num_valid as f32 {
+                flags[i] = Flag::Fail;
+                num_removed += 1;
+            }
+        }
+
+        if num_removed == 0 {
+            break;
+        }
+    }
+
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SeriesCache, Timestamp};
+    use chronoutil::RelativeDuration;
+
+    #[test]
+    fn test_spatiotemporal_buddy_check() {
+        const N_STATIONS: usize = 6;
+        const N_TIMES: usize = 24;
+
+        let series = (0..N_STATIONS)
+            .map(|station| SeriesCache {
+                start_time: Timestamp(0),
+                period: RelativeDuration::hours(1),
+                values: (0..N_TIMES)
+                    .map(|t| {
+                        let noise = ((station * 7 + t * 3) % 5) as f32 * 0.2 - 0.4;
+                        let bias = if station == 2 { 3. } else { 0. };
+                        // station 5 has a single large deviation
+                        let spike = if station == 5 && t == 10 { 10. } else { 0. };
+                        Some(10. + noise + bias + spike)
+                    })
+                    .collect(),
+                num_leading_points: 0,
+                num_trailing_points: 0,
+            })
+            .collect();
+
+        let data = SpatioTemporalCache::new(
+            vec![60.; N_STATIONS],
+            (0..N_STATIONS).map(|i| 10. + i as f32 * 0.01).collect(),
+            vec![0.; N_STATIONS],
+            series,
+        )
+        .unwrap();
+
+        assert_eq!(
+            spatiotemporal_buddy_check(&data, &[10000.], &[3], 2., 0.8, -1., 0., 2, None).unwrap(),
+            [
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Fail,
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Pass
+            ]
+        );
+        assert_eq!(
+            spatiotemporal_buddy_check(&data, &[10000.], &[6], 2., 0.8, -1., 0., 2, None).unwrap(),
+            [Flag::Isolated; N_STATIONS]
+        );
+    }
+
+    #[test]
+    fn test_spatiotemporal_buddy_check_order() {
+        // stations along a line, with the ones at the ends having only one buddy within 900m
+        let lons = [10., 10.015, 10.025, 10.035, 10.045];
+        let data = |lons: Vec<f32>| {
+            let n = lons.len();
+            SpatioTemporalCache::new(
+                vec![60.; n],
+                lons,
+                vec![0.; n],
+                vec![
+                    SeriesCache {
+                        start_time: Timestamp(0),
+                        period: RelativeDuration::hours(1),
+                        values: vec![Some(10.); 4],
+                        num_leading_points: 0,
+                        num_trailing_points: 0,
+                    };
+                    n
+                ],
+            )
+            .unwrap()
+        };
+        let run = |data: &SpatioTemporalCache| {
+            spatiotemporal_buddy_check(data, &[900.], &[2], 2., 0.8, -1., 0., 2, None).unwrap()
+        };
+
+        // isolated stations are still buddies for the others
+        let flags = run(&data(lons.to_vec()));
+        assert_eq!(
+            flags,
+            [
+                Flag::Isolated,
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Isolated
+            ]
+        );
+
+        let mut reversed = run(&data(lons.iter().rev().copied().collect()));
+        reversed.reverse();
+        assert_eq!(reversed, flags);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Error, Flag, SeriesCache};
+use crate::{Error, Flag, Float, SeriesCache};
 
 /// Timeseries QC test that compares each observation against its immediate predecessor.
 ///
@@ -13,7 +13,7 @@ use crate::{Error, Flag, SeriesCache};
 ///
 /// - data is invalid
 /// - data has `num_leading_points` <= 1
-pub fn step_check(data: &SeriesCache, high: f32, max: f32) -> Result<Vec<Flag>, Error> {
+pub fn step_check<T: Float>(data: &SeriesCache<T>, high: T, max: T) -> Result<Vec<Flag>, Error> {
     let (leading_trim, lead_overflow) = data.num_leading_points.overflowing_sub(1);
 
     if lead_overflow || (leading_trim + 2) as usize > data.values.len() {
@@ -30,7 +30,7 @@ pub fn step_check(data: &SeriesCache, high: f32, max: f32) -> Result<Vec<Flag>,
             if data.contains(&None) {
                 return Flag::DataMissing;
             }
-            let data: Vec<f32> = data.iter().map(|opt| opt.unwrap()).collect();
+            let data: Vec<T> = data.iter().map(|opt| opt.unwrap()).collect();
 
             if (data[0] - data[1]).abs() > high {
                 return Flag::Warn;
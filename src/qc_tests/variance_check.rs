@@ -0,0 +1,166 @@
+use crate::{Error, Flag, Float, SeriesCache};
+
+/// Measure of variability used by [`variance_check`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Variability {
+    /// Population standard deviation of the values in the window
+    StandardDeviation,
+    /// Sum of the absolute differences between successive values in the window
+    AbsoluteDifferenceSum,
+}
+
+impl Variability {
+    fn compute<T: Float>(&self, window: &[T]) -> T {
+        match self {
+            Variability::StandardDeviation => {
+                let n = T::from_f32(window.len() as f32);
+                let mean = window.iter().sum::<T>() / n;
+                (window.iter().map(|x| (*x - mean).powi(2)).sum::<T>() / n).sqrt()
+            }
+            Variability::AbsoluteDifferenceSum => window
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).abs())
+                .sum(),
+        }
+    }
+}
+
+/// Timeseries QC test that checks the variability of the series in a rolling window around each
+/// observation.
+///
+/// For each observation, a window is taken containing the observation itself plus `half_window`
+/// observations either side of it, and the variability of the window is computed according to
+/// `measure`. The observation will be flagged as follows
+/// - If values are missing for any observation in the window: DataMissing.
+/// - If the variability is greater than `max`: Fail. This indicates a noisy sensor.
+/// - If the variability is less than `min`: Fail. This indicates a stuck sensor.
+/// - Else: Pass
+///
+/// As `half_window` neighbours either side of each observation are needed, the [`SeriesCache`]
+/// provided must have `num_leading_points` and `num_trailing_points` >= `half_window`.
+///
+/// ## Errors
+///
+/// - data is invalid
+/// - `half_window` is 0
+/// - data has `num_leading_points` < `half_window`
+/// - data has `num_trailing_points` < `half_window`
+pub fn variance_check<T: Float>(
+    data: &SeriesCache<T>,
+    half_window: u8,
+    measure: Variability,
+    min: T,
+    max: T,
+) -> Result<Vec<Flag>, Error> {
+    if half_window == 0 {
+        return Err(Error::InvalidArg(
+            "half_window".to_string(),
+            "must be > 0".to_string(),
+        ));
+    }
+
+    let (leading_trim, lead_overflow) = data.num_leading_points.overflowing_sub(half_window);
+    let (trailing_trim, trail_overflow) = data.num_trailing_points.overflowing_sub(half_window);
+    let window_size = 2 * half_window as usize + 1;
+
+    if lead_overflow
+        || trail_overflow
+        || leading_trim as usize + trailing_trim as usize + window_size > data.values.len()
+    {
+        return Err(Error::InvalidInputShape("data".to_string()));
+    }
+
+    let trimmed = &data.values[leading_trim as usize..(data.values.len() - trailing_trim as usize)];
+
+    let windows = trimmed.windows(window_size);
+
+    Ok(windows
+        .map(|data| {
+            if data.contains(&None) {
+                return Flag::DataMissing;
+            }
+            let data: Vec<T> = data.iter().map(|opt| opt.unwrap()).collect();
+
+            let variability = measure.compute(&data);
+
+            if variability > max || variability < min {
+                return Flag::Fail;
+            }
+            Flag::Pass
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Timestamp;
+    use chronoutil::RelativeDuration;
+
+    #[test]
+    fn test_variance_check() {
+        let data = SeriesCache {
+            start_time: Timestamp(0),
+            period: RelativeDuration::hours(1),
+            values: vec![
+                Some(1.),
+                Some(1.1),
+                Some(0.9),
+                Some(1.),
+                Some(6.),
+                Some(-3.),
+                Some(4.),
+                Some(1.),
+                None,
+                Some(1.),
+                Some(1.),
+                Some(1.),
+                Some(1.),
+            ],
+            num_leading_points: 1,
+            num_trailing_points: 1,
+        };
+
+        assert_eq!(
+            variance_check(&data, 1, Variability::StandardDeviation, 0.01, 2.).unwrap(),
+            [
+                Flag::Pass,
+                Flag::Pass,
+                Flag::Fail,
+                Flag::Fail,
+                Flag::Fail,
+                Flag::Fail,
+                Flag::DataMissing,
+                Flag::DataMissing,
+                Flag::DataMissing,
+                Flag::Fail,
+                Flag::Fail,
+            ]
+        );
+        assert!(matches!(
+            variance_check(&data, 2, Variability::AbsoluteDifferenceSum, 0., 10.),
+            Err(Error::InvalidInputShape(_))
+        ));
+    }
+
+    #[test]
+    fn test_variance_check_f64() {
+        // the noise is far below the resolution of f32 at this magnitude
+        let data = SeriesCache {
+            start_time: Timestamp(0),
+            period: RelativeDuration::hours(1),
+            values: [0., 0.1, -0.1, 0.05, 0.05, 0.05, 0.05]
+                .iter()
+                .map(|noise| Some(1e8_f64 + noise))
+                .collect(),
+            num_leading_points: 1,
+            num_trailing_points: 1,
+        };
+
+        assert_eq!(
+            variance_check(&data, 1, Variability::StandardDeviation, 0.01, 1.).unwrap(),
+            [Flag::Pass, Flag::Pass, Flag::Pass, Flag::Fail, Flag::Fail]
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use crate::{Error, Flag, Float, SeriesCache};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Run a timeseries QC test on many series in one call
+///
+/// `test` is run on each series in `data`, and the results are returned in the same order. Errors
+/// are reported individually for each series, so one invalid series does not prevent the rest
+/// from being QCed. Series sharing a `start_time` and `period` can be held in a
+/// [`SpatioTemporalCache`](crate::SpatioTemporalCache), and passed here using its
+/// [`series`](crate::SpatioTemporalCache::series) method.
+///
+/// With the `parallel` feature enabled, the series are QCed in parallel using
+/// [rayon](https://docs.rs/rayon).
+///
+/// ```
+/// use chronoutil::RelativeDuration;
+/// use olympian::{batch_check, step_check, Flag, SeriesCache, Timestamp};
+///
+/// let series = |values: Vec<Option<f32>>| SeriesCache {
+///     start_time: Timestamp(0),
+///     period: RelativeDuration::hours(1),
+///     values,
+///     num_leading_points: 1,
+///     num_trailing_points: 0,
+/// };
+///
+/// let results = batch_check(
+///     &[series(vec![Some(1.), Some(1.)]), series(vec![Some(1.)])],
+///     |data| step_check(data, 1., 2.),
+/// );
+///
+/// assert_eq!(results[0].as_ref().unwrap(), &[Flag::Pass]);
+/// assert!(results[1].is_err());
+/// ```
+pub fn batch_check<T, F>(data: &[SeriesCache<T>], test: F) -> Vec<Result<Vec<Flag>, Error>>
+where
+    T: Float,
+    F: Fn(&SeriesCache<T>) -> Result<Vec<Flag>, Error> + Send + Sync,
+{
+    #[cfg(feature = "parallel")]
+    let iter = data.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let iter = data.iter();
+
+    iter.map(test).collect()
+}
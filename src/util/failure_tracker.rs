@@ -0,0 +1,221 @@
+use crate::{Error, Flag};
+use std::{collections::HashMap, hash::Hash};
+
+/// Record of a station's recent results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct FailureHistory {
+    /// One bit per time step, set if the station failed, with the most recent in the lowest bit
+    failures: u64,
+    /// The number of time steps recorded, up to the tracker's window
+    len: u8,
+}
+
+/// Tracker of stations' results across successive runs of a spatial QC test, used to distinguish
+/// one-off failures from chronic ones.
+///
+/// After each run of a test such as [`buddy_check`](crate::buddy_check) or [`sct`](crate::sct),
+/// the flags are passed to [`update`](FailureTracker::update) along with an ID for each station.
+/// The tracker remembers whether each station failed in each of its last `window` time steps,
+/// and stations that failed in more than `max_failures` of them are considered chronic failures.
+///
+/// The state is kept compact, using one bit per station per time step, so it can be stored
+/// between runs. With the `serde` feature enabled, the tracker implements `Serialize` and
+/// `Deserialize`.
+///
+/// Station IDs can be of any hashable type, such as the index of the station in the input or a
+/// string identifier. Indices should only be used if they are stable between runs.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        try_from = "FailureTrackerState<K>",
+        bound(deserialize = "K: Eq + Hash + Clone + serde::Deserialize<'de>")
+    )
+)]
+pub struct FailureTracker<K: Eq + Hash> {
+    window: u8,
+    max_failures: u8,
+    history: HashMap<K, FailureHistory>,
+}
+
+/// The fields of a [`FailureTracker`], deserialized before they are validated
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct FailureTrackerState<K: Eq + Hash> {
+    window: u8,
+    max_failures: u8,
+    history: HashMap<K, FailureHistory>,
+}
+
+#[cfg(feature = "serde")]
+impl<K: Eq + Hash + Clone> TryFrom<FailureTrackerState<K>> for FailureTracker<K> {
+    type Error = Error;
+
+    fn try_from(state: FailureTrackerState<K>) -> Result<Self, Error> {
+        let mut tracker = Self::new(state.window, state.max_failures)?;
+
+        let mask = tracker.mask();
+        if state
+            .history
+            .values()
+            .any(|history| history.failures & !mask != 0 || history.len > state.window)
+        {
+            return Err(Error::InvalidArg(
+                String::from("history"),
+                String::from("must not be longer than window"),
+            ));
+        }
+        tracker.history = state.history;
+
+        Ok(tracker)
+    }
+}
+
+impl<K: Eq + Hash + Clone> FailureTracker<K> {
+    /// Create a new FailureTracker with no history
+    ///
+    /// ## Errors
+    ///
+    /// - `window` is 0 or greater than 64
+    /// - `max_failures` >= `window`
+    pub fn new(window: u8, max_failures: u8) -> Result<Self, Error> {
+        if window == 0 || window > 64 {
+            return Err(Error::InvalidArg(
+                String::from("window"),
+                String::from("must be within [1, 64]"),
+            ));
+        }
+        if max_failures >= window {
+            return Err(Error::InvalidArg(
+                String::from("max_failures"),
+                String::from("must be < window"),
+            ));
+        }
+
+        Ok(Self {
+            window,
+            max_failures,
+            history: HashMap::new(),
+        })
+    }
+
+    /// Record the flags from a run of a QC test, and classify the failures
+    ///
+    /// `ids` and `flags` give the ID and flag of each station in the run. Stations absent from
+    /// the run keep their history unchanged. Only `Flag::Fail` counts as a failure. The
+    /// returned flags are, for each station in the run
+    /// - Fail: the station failed in more than `max_failures` of its last `window` time steps.
+    ///   This is returned even if the station did not fail in this run.
+    /// - Warn: the station failed in this run, but not often enough to be a chronic failure.
+    /// - Otherwise, the flag passed in.
+    ///
+    /// ## Errors
+    ///
+    /// - `ids` and `flags` have different lengths
+    pub fn update(&mut self, ids: &[K], flags: &[Flag]) -> Result<Vec<Flag>, Error> {
+        if ids.len() != flags.len() {
+            return Err(Error::InvalidInputShape(String::from("flags")));
+        }
+
+        let mask = self.mask();
+
+        Ok(ids
+            .iter()
+            .zip(flags)
+            .map(|(id, flag)| {
+                let history = self.history.entry(id.clone()).or_default();
+                history.failures = ((history.failures << 1) | (*flag == Flag::Fail) as u64) & mask;
+                history.len = (history.len + 1).min(self.window);
+
+                if history.failures.count_ones() > self.max_failures as u32 {
+                    Flag::Fail
+                } else if *flag == Flag::Fail {
+                    Flag::Warn
+                } else {
+                    *flag
+                }
+            })
+            .collect())
+    }
+
+    /// Get the number of failures of a station in its last `window` time steps, and the number
+    /// of time steps recorded, or `None` if the station has no history
+    pub fn failures(&self, id: &K) -> Option<(u32, u8)> {
+        self.history
+            .get(id)
+            .map(|history| (history.failures.count_ones(), history.len))
+    }
+
+    /// the bits of a station's history that fall within the window
+    fn mask(&self) -> u64 {
+        u64::MAX >> (64 - self.window as u32)
+    }
+
+    /// Forget the history of a station, for example after its sensor has been replaced
+    pub fn reset(&mut self, id: &K) {
+        self.history.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_tracker() {
+        let mut tracker = FailureTracker::new(4, 2).unwrap();
+        let ids = ["a", "b"];
+
+        assert_eq!(
+            tracker.update(&ids, &[Flag::Fail, Flag::Fail]).unwrap(),
+            [Flag::Warn, Flag::Warn]
+        );
+        assert_eq!(
+            tracker.update(&ids, &[Flag::Fail, Flag::Pass]).unwrap(),
+            [Flag::Warn, Flag::Pass]
+        );
+        assert_eq!(
+            tracker.update(&ids, &[Flag::Fail, Flag::Isolated]).unwrap(),
+            [Flag::Fail, Flag::Isolated]
+        );
+        assert_eq!(
+            tracker.update(&ids[..1], &[Flag::Pass]).unwrap(),
+            [Flag::Fail]
+        );
+        assert_eq!(tracker.failures(&"a"), Some((3, 4)));
+        assert_eq!(tracker.failures(&"b"), Some((1, 3)));
+
+        // the oldest failures drop out of the window
+        assert_eq!(
+            tracker.update(&ids[..1], &[Flag::Pass]).unwrap(),
+            [Flag::Pass]
+        );
+
+        tracker.reset(&"a");
+        assert_eq!(tracker.failures(&"a"), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_failure_tracker_serde() {
+        let mut tracker = FailureTracker::new(4, 2).unwrap();
+        tracker
+            .update(&["a", "b"], &[Flag::Fail, Flag::Pass])
+            .unwrap();
+
+        let json = serde_json::to_string(&tracker).unwrap();
+        let deserialized: FailureTracker<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.failures(&"a".to_string()), Some((1, 1)));
+        assert_eq!(deserialized.failures(&"b".to_string()), Some((0, 1)));
+
+        for invalid in [
+            r#"{"window":0,"max_failures":0,"history":{}}"#,
+            r#"{"window":4,"max_failures":4,"history":{}}"#,
+            r#"{"window":4,"max_failures":2,"history":{"a":{"failures":16,"len":4}}}"#,
+        ] {
+            assert!(serde_json::from_str::<FailureTracker<String>>(invalid).is_err());
+        }
+    }
+}
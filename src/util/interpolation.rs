@@ -0,0 +1,203 @@
+use crate::{Error, Flag, Float, SeriesCache};
+
+/// Method for filling gaps in a series, see [`SeriesCache::fill_gaps`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Interpolation {
+    /// Linear interpolation between the values either side of the gap
+    Linear,
+    /// Use the value nearest in time, preferring the earlier value when both are equally near
+    Nearest,
+}
+
+impl<T: Float> SeriesCache<T> {
+    /// Fill gaps in the series by interpolation
+    ///
+    /// Only gaps with values on both sides are filled, gaps at the start or end of the series are
+    /// left as they are. If `max_gap` is provided, gaps of more than `max_gap` consecutive missing
+    /// values are also left unfilled.
+    ///
+    /// Returns the filled series, along with a mask the same length as `values`, which is true
+    /// where the value was synthesized.
+    pub fn fill_gaps(
+        &self,
+        method: Interpolation,
+        max_gap: Option<usize>,
+    ) -> (SeriesCache<T>, Vec<bool>) {
+        let mut filled = self.clone();
+        let mut mask = vec![false; self.values.len()];
+
+        // index of the last value before the current gap
+        let mut last_valid: Option<usize> = None;
+
+        for i in 0..self.values.len() {
+            let Some(after) = self.values[i] else {
+                continue;
+            };
+
+            if let Some(prev) = last_valid {
+                let gap = i - prev - 1;
+                if gap > 0 && max_gap.is_none_or(|max_gap| gap <= max_gap) {
+                    let before = self.values[prev].unwrap();
+
+                    let gap_values = &mut filled.values[(prev + 1)..i];
+                    let gap_mask = &mut mask[(prev + 1)..i];
+
+                    for (k, (value, synthetic)) in gap_values.iter_mut().zip(gap_mask).enumerate() {
+                        // distances to the values before and after the gap
+                        let (dist_before, dist_after) = (k + 1, gap - k);
+
+                        *value = Some(match method {
+                            Interpolation::Linear => {
+                                let fraction = T::from_f32(dist_before as f32 / (gap + 1) as f32);
+                                before + (after - before) * fraction
+                            }
+                            Interpolation::Nearest => {
+                                if dist_before <= dist_after {
+                                    before
+                                } else {
+                                    after
+                                }
+                            }
+                        });
+                        *synthetic = true;
+                    }
+                }
+            }
+
+            last_valid = Some(i);
+        }
+
+        (filled, mask)
+    }
+}
+
+/// Run a timeseries QC test on a series with its gaps filled by interpolation
+///
+/// This allows tests to QC observations that would otherwise be flagged DataMissing because one
+/// of their neighbours is missing. The gaps are filled according to `method` and `max_gap`, as in
+/// [`SeriesCache::fill_gaps`], and `test` is run on the filled series. Synthesized points are
+/// then flagged [`Flag::Synthetic`], whatever flag the test gave them.
+///
+/// ```
+/// use chronoutil::RelativeDuration;
+/// use olympian::{qc_filled, step_check, Flag, Interpolation, SeriesCache, Timestamp};
+///
+/// let data = SeriesCache {
+///     start_time: Timestamp(0),
+///     period: RelativeDuration::hours(1),
+///     values: vec![Some(1.), None, Some(1.), Some(1.)],
+///     num_leading_points: 1,
+///     num_trailing_points: 0,
+/// };
+///
+/// assert_eq!(
+///     qc_filled(&data, Interpolation::Linear, Some(1), |data| step_check(data, 1., 2.))
+///         .unwrap(),
+///     [Flag::Synthetic, Flag::Pass, Flag::Pass]
+/// );
+/// ```
+///
+/// ## Errors
+///
+/// - `test` returned an error, or did not return one flag for each point in the QC window
+pub fn qc_filled<T, F>(
+    data: &SeriesCache<T>,
+    method: Interpolation,
+    max_gap: Option<usize>,
+    test: F,
+) -> Result<Vec<Flag>, Error>
+where
+    T: Float,
+    F: FnOnce(&SeriesCache<T>) -> Result<Vec<Flag>, Error>,
+{
+    let (filled, mask) = data.fill_gaps(method, max_gap);
+    let mut flags = test(&filled)?;
+
+    let start = data.num_leading_points as usize;
+    let end = data
+        .values
+        .len()
+        .checked_sub(data.num_trailing_points as usize)
+        .filter(|end| *end >= start)
+        .ok_or_else(|| Error::InvalidInputShape(String::from("data")))?;
+    if flags.len() != end - start {
+        return Err(Error::InvalidInputShape(String::from("flags")));
+    }
+
+    for (flag, synthetic) in flags.iter_mut().zip(&mask[start..end]) {
+        if *synthetic {
+            *flag = Flag::Synthetic;
+        }
+    }
+
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Timestamp;
+    use chronoutil::RelativeDuration;
+
+    #[test]
+    fn test_fill_gaps() {
+        let data = SeriesCache {
+            start_time: Timestamp(0),
+            period: RelativeDuration::hours(1),
+            values: vec![
+                None,
+                Some(0.),
+                None,
+                None,
+                None,
+                Some(4.),
+                None,
+                Some(6.),
+                None,
+            ],
+            num_leading_points: 0,
+            num_trailing_points: 0,
+        };
+
+        let (filled, mask) = data.fill_gaps(Interpolation::Linear, None);
+        assert_eq!(
+            filled.values,
+            [
+                None,
+                Some(0.),
+                Some(1.),
+                Some(2.),
+                Some(3.),
+                Some(4.),
+                Some(5.),
+                Some(6.),
+                None
+            ]
+        );
+        assert_eq!(
+            mask,
+            [false, false, true, true, true, false, true, false, false]
+        );
+
+        let (filled, mask) = data.fill_gaps(Interpolation::Nearest, Some(2));
+        assert_eq!(
+            filled.values,
+            [
+                None,
+                Some(0.),
+                None,
+                None,
+                None,
+                Some(4.),
+                Some(4.),
+                Some(6.),
+                None
+            ]
+        );
+        assert_eq!(
+            mask,
+            [false, false, false, false, false, false, true, false, false]
+        );
+    }
+}
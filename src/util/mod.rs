@@ -1,10 +1,68 @@
 //! Utility types and functions for QC tests
 
+pub mod batch;
+pub mod failure_tracker;
+pub mod interpolation;
+pub mod parallel;
+mod resample;
+pub mod solar;
 pub mod spatial_tree;
+pub mod streaming;
 use spatial_tree::SpatialTree;
 
 use crate::Error;
+use chrono::{DateTime, Utc};
 use chronoutil::RelativeDuration;
+use std::{fmt::Debug, iter::Sum};
+
+/// Floating point type that values can be QCed in, implemented for `f32` and `f64`
+///
+/// Caches and QC tests are generic over this, defaulting to `f32`. `f64` can be used where more
+/// precision is needed, e.g. for pressure in Pa. Parameters of QC tests in the unit of the
+/// observations (ou) have the same type as the observations, while coordinates, distances, and
+/// other parameters, such as thresholds in standard deviations, are always `f32`.
+pub trait Float:
+    num_traits::Float
+    + num_traits::NumAssign
+    + faer::RealField
+    + Sum
+    + for<'a> Sum<&'a Self>
+    + Debug
+    + Send
+    + Sync
+    + 'static
+{
+    /// Convert from an `f32`, such as a constant
+    fn from_f32(value: f32) -> Self;
+    /// Convert from an `f64`, rounding if needed
+    fn from_f64(value: f64) -> Self;
+    /// Convert to an `f64`
+    fn as_f64(self) -> f64;
+}
+
+impl Float for f32 {
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Float for f64 {
+    fn from_f32(value: f32) -> Self {
+        value as f64
+    }
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+    fn as_f64(self) -> f64 {
+        self
+    }
+}
 
 /// Flag indicating result of a QC test for a given data point
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -30,15 +88,97 @@ pub enum Flag {
     ///
     /// Only relevant for spatial tests
     Isolated,
+    /// The data point was missing, and a synthetic value was used in its place
+    ///
+    /// The synthetic value was not itself QCed, but was used as context to QC
+    /// other data points. See [`qc_filled`](crate::qc_filled)
+    Synthetic,
 }
 
 /// Unix timestamp, inner i64 is seconds since unix epoch
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Timestamp(pub i64);
 
+impl Timestamp {
+    pub(crate) fn to_datetime(self) -> Result<DateTime<Utc>, Error> {
+        DateTime::from_timestamp(self.0, 0)
+            .ok_or_else(|| Error::InvalidArg("timestamp".to_string(), "out of range".to_string()))
+    }
+
+    /// Shift the timestamp by `n` periods
+    ///
+    /// This computes `self + period * n` in one step, rather than adding `period` `n` times, so
+    /// that month arithmetic does not drift, e.g. adding 2 months to the 31st of January gives
+    /// the 31st of March, not the 28th.
+    pub(crate) fn add_periods(self, period: RelativeDuration, n: i64) -> Result<Self, Error> {
+        let n = i32::try_from(n)
+            .map_err(|_| Error::InvalidArg("n".to_string(), "out of range".to_string()))?;
+
+        Ok(Timestamp((self.to_datetime()? + period * n).timestamp()))
+    }
+}
+
+/// find the smallest `n` such that `start + period * n` is after `time`
+pub(crate) fn first_index_after(
+    start: Timestamp,
+    period: RelativeDuration,
+    time: Timestamp,
+) -> Result<i64, Error> {
+    let period_seconds = start.add_periods(period, 1)?.0 - start.0;
+    if period_seconds <= 0 {
+        return Err(Error::InvalidArg(
+            "period".to_string(),
+            "must be positive".to_string(),
+        ));
+    }
+
+    // this estimate is exact for fixed length periods, but can be a little off for periods
+    // containing months, so we correct it afterwards
+    let mut n = (time.0 - start.0).div_euclid(period_seconds) + 1;
+    while start.add_periods(period, n - 1)? > time {
+        n -= 1;
+    }
+    while start.add_periods(period, n)? <= time {
+        n += 1;
+    }
+
+    Ok(n)
+}
+
+/// Method for aggregating several values into one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Aggregation {
+    /// Sum of the values, e.g. for precipitation
+    Sum,
+    /// Arithmetic mean of the values
+    Mean,
+    /// Minimum of the values
+    Min,
+    /// Maximum of the values
+    Max,
+    /// The last of the values
+    Last,
+}
+
+impl Aggregation {
+    /// aggregate a non-empty slice of values
+    pub(crate) fn apply<T: Float>(&self, values: &[T]) -> T {
+        match self {
+            Aggregation::Sum => values.iter().sum(),
+            Aggregation::Mean => values.iter().sum::<T>() / T::from_f32(values.len() as f32),
+            Aggregation::Min => values.iter().copied().fold(T::infinity(), T::min),
+            Aggregation::Max => values.iter().copied().fold(T::neg_infinity(), T::max),
+            Aggregation::Last => values[values.len() - 1],
+        }
+    }
+}
+
 /// Container of series data
+///
+/// Values are `f32` by default, but can be any [`Float`].
 #[derive(Debug, Clone, PartialEq)]
-pub struct SeriesCache {
+pub struct SeriesCache<T = f32> {
     /// Time of the first observation in data
     pub start_time: Timestamp,
     /// Period of the timeseries, i.e. the time gap between successive elements
@@ -46,7 +186,7 @@ pub struct SeriesCache {
     /// Data points of the timeseries in chronological order
     ///
     /// `None`s represent gaps in the series
-    pub values: Vec<Option<f32>>,
+    pub values: Vec<Option<T>>,
     /// The number of extra points in the series before the data to be QCed
     ///
     /// These points are needed because certain timeseries tests need more
@@ -59,45 +199,387 @@ pub struct SeriesCache {
     pub num_trailing_points: u8,
 }
 
+impl<T: Float> SeriesCache<T> {
+    /// Create a new SeriesCache from timestamped observations
+    ///
+    /// The data to be QCed runs from `qc_start` to `qc_end` inclusive, with elements every
+    /// `period` starting from `qc_start`. `num_leading_points` elements before it and
+    /// `num_trailing_points` elements after it are added for context. Observations can be
+    /// provided in any order, and those outside the resulting series are ignored. Gaps in the
+    /// observations are filled with `None`.
+    ///
+    /// ```
+    /// use chronoutil::RelativeDuration;
+    /// use olympian::{SeriesCache, Timestamp};
+    ///
+    /// let series = SeriesCache::from_observations(
+    ///     &[(Timestamp(7200), 3.), (Timestamp(0), 1.), (Timestamp(10800), 4.)],
+    ///     RelativeDuration::hours(1),
+    ///     Timestamp(3600),
+    ///     Timestamp(7200),
+    ///     1,
+    ///     1,
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(series.start_time, Timestamp(0));
+    /// assert_eq!(series.values, [Some(1.), None, Some(3.), Some(4.)]);
+    /// ```
+    ///
+    /// ## Errors
+    ///
+    /// - `qc_end` is before `qc_start`
+    /// - the period is not positive
+    /// - more than one observation is given for the same time
+    /// - an observation's time is not on the grid of times defined by `qc_start` and `period`
+    /// - the timestamps involved are outside the range that can be represented
+    pub fn from_observations(
+        observations: &[(Timestamp, T)],
+        period: RelativeDuration,
+        qc_start: Timestamp,
+        qc_end: Timestamp,
+        num_leading_points: u8,
+        num_trailing_points: u8,
+    ) -> Result<Self, Error> {
+        if qc_end < qc_start {
+            return Err(Error::InvalidArg(
+                "qc_end".to_string(),
+                "must not be before qc_start".to_string(),
+            ));
+        }
+
+        let num_qc_points = first_index_after(qc_start, period, qc_end)?;
+        let start_time = qc_start.add_periods(period, -(num_leading_points as i64))?;
+        let end_time =
+            qc_start.add_periods(period, num_qc_points + num_trailing_points as i64 - 1)?;
+        let len =
+            num_leading_points as usize + num_qc_points as usize + num_trailing_points as usize;
+
+        let mut values = vec![None; len];
+        for (time, value) in observations {
+            if *time < start_time || *time > end_time {
+                continue;
+            }
+
+            let index = first_index_after(start_time, period, *time)? - 1;
+            if start_time.add_periods(period, index)? != *time {
+                return Err(Error::OffGridTimestamp(*time));
+            }
+            if values[index as usize].replace(*value).is_some() {
+                return Err(Error::DuplicateTimestamp(*time));
+            }
+        }
+
+        Ok(Self {
+            start_time,
+            period,
+            values,
+            num_leading_points,
+            num_trailing_points,
+        })
+    }
+
+    /// get the time of the element at `index`
+    pub(crate) fn time_at(&self, index: i64) -> Result<Timestamp, Error> {
+        self.start_time.add_periods(self.period, index)
+    }
+}
+
 /// Container of spatial data
 ///
 /// This contains the values of the data along with an [R*-tree](https://en.wikipedia.org/wiki/R*-tree)
 /// used to spatially index them. A [`new`](SpatialCache::new) method is provided to avoid the
 /// need to construct an R*-tree manually, instead users need only provide the latitude, longitude
 /// (in degrees), and elevation (in meters) for each value.
+///
+/// Where positions are already in a projected coordinate system, the cache can instead be
+/// constructed with [`from_projected`](SpatialCache::from_projected), from the northing and
+/// easting (in meters) of each value. Distances are then measured in the plane of the projection,
+/// rather than along great circles, and wherever a latitude and longitude would be used, such as
+/// in the neighbour queries, the northing and easting are used instead.
+///
+/// Values are `f32` by default, but can be any [`Float`].
 #[derive(Debug, Clone)]
-pub struct SpatialCache {
+pub struct SpatialCache<T = f32> {
     /// an [R*-tree](https://en.wikipedia.org/wiki/R*-tree) used to spatially
     /// index the data
     pub(crate) rtree: SpatialTree,
     /// Data points in the spatial slice
-    pub values: Vec<f32>,
+    pub values: Vec<T>,
 }
 
-impl SpatialCache {
+impl<T: Float> SpatialCache<T> {
     /// Create a new SpatialCache without manually constructing the R*-tree
-    pub fn new(lats: Vec<f32>, lons: Vec<f32>, elevs: Vec<f32>, values: Vec<f32>) -> Self {
-        // TODO: ensure vecs have same size
-        Self {
+    ///
+    /// The latitude, longitude (in degrees), elevation (in meters) and value of each point are
+    /// given by the elements at the same index in each vector.
+    ///
+    /// ## Errors
+    ///
+    /// - the vectors do not all have the same length
+    /// - a latitude is not between -90 and 90, or a longitude is not between -360 and 360
+    pub fn new(
+        lats: Vec<f32>,
+        lons: Vec<f32>,
+        elevs: Vec<f32>,
+        values: Vec<T>,
+    ) -> Result<Self, Error> {
+        Self::check_shape(&lats, &lons, &elevs, &values)?;
+        check_latlons(&lats, &lons)?;
+
+        Ok(Self {
             rtree: SpatialTree::from_latlons(lats, lons, elevs),
             values,
+        })
+    }
+
+    /// Create a new SpatialCache from positions in a projected coordinate system
+    ///
+    /// The northing, easting, and elevation (all in meters) of each value are given by the
+    /// elements at the same index in each vector.
+    ///
+    /// Positions are held as `f32`, which at the magnitude of typical northings (millions of
+    /// meters) can only resolve them to about half a meter, so distances between points are
+    /// only accurate to within a meter or so. If that matters, subtract a local origin from the
+    /// northings and eastings before constructing the cache.
+    ///
+    /// ## Errors
+    ///
+    /// - the vectors do not all have the same length
+    pub fn from_projected(
+        northings: Vec<f32>,
+        eastings: Vec<f32>,
+        elevs: Vec<f32>,
+        values: Vec<T>,
+    ) -> Result<Self, Error> {
+        Self::check_shape(&northings, &eastings, &elevs, &values)?;
+
+        Ok(Self {
+            rtree: SpatialTree::from_projected(northings, eastings, elevs),
+            values,
+        })
+    }
+
+    fn check_shape(lats: &[f32], lons: &[f32], elevs: &[f32], values: &[T]) -> Result<(), Error> {
+        if lons.len() != lats.len() {
+            return Err(Error::InvalidInputShape("lons".to_string()));
+        }
+        if elevs.len() != lats.len() {
+            return Err(Error::InvalidInputShape("elevs".to_string()));
         }
+        if values.len() != lats.len() {
+            return Err(Error::InvalidInputShape("values".to_string()));
+        }
+        Ok(())
     }
 
     // TODO: rename to values?
     /// Get a reference to the values held inside the SpatialCache
-    pub fn data(&self) -> &Vec<f32> {
+    pub fn data(&self) -> &Vec<T> {
         &self.values
     }
+
+    /// Get the latitude, longitude (in degrees), and elevation (in meters) of the point at
+    /// `index`, or None if there is no such point
+    ///
+    /// For a cache constructed with [`from_projected`](SpatialCache::from_projected), the
+    /// northing and easting are returned in place of the latitude and longitude.
+    pub fn coords(&self, index: usize) -> Option<(f32, f32, f32)> {
+        (index < self.rtree.lats.len()).then(|| self.rtree.get_coords_at_index(index))
+    }
+
+    /// Find the points within `radius` \[m\] of a location, ordered by distance
+    ///
+    /// Points at the location itself are included, so when looking for the neighbours of a point
+    /// in the cache, the point itself will be found at a distance of 0.
+    pub fn neighbours_within(&self, lat: f32, lon: f32, radius: f32) -> Vec<Neighbour> {
+        self.rtree.neighbours_within(lat, lon, radius)
+    }
+
+    /// Find the `num_max` points nearest to a location, ordered by distance
+    ///
+    /// Only points within `radius` \[m\] are considered, which can be set to [`f32::INFINITY`] to
+    /// consider all points. As in [`neighbours_within`](SpatialCache::neighbours_within), points
+    /// at the location itself are included.
+    pub fn nearest_neighbours(
+        &self,
+        lat: f32,
+        lon: f32,
+        num_max: usize,
+        radius: f32,
+    ) -> Vec<Neighbour> {
+        self.rtree.nearest_neighbours(lat, lon, num_max, radius)
+    }
+
+    /// Find the points within `radius` \[m\] of a location, whose elevation differs from `elev`
+    /// by at most `max_elev_diff` \[m\], ordered by distance
+    ///
+    /// As in [`neighbours_within`](SpatialCache::neighbours_within), points at the location
+    /// itself are included.
+    pub fn neighbours_within_elev_band(
+        &self,
+        lat: f32,
+        lon: f32,
+        elev: f32,
+        radius: f32,
+        max_elev_diff: f32,
+    ) -> Vec<Neighbour> {
+        let mut neighbours = self.rtree.neighbours_within(lat, lon, radius);
+        neighbours
+            .retain(|neighbour| (self.rtree.elevs[neighbour.index] - elev).abs() <= max_elev_diff);
+
+        neighbours
+    }
+}
+
+/// A point found by a neighbour query on a [`SpatialCache`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbour {
+    /// Index of the point in the cache
+    pub index: usize,
+    /// Distance to the point \[m\], along a great circle, or in the plane of the projection for a
+    /// cache constructed with `from_projected`
+    pub distance: f32,
+}
+
+/// Container of spatio-temporal data
+///
+/// This contains a timeseries for each of a set of stations, along with an
+/// [R*-tree](https://en.wikipedia.org/wiki/R*-tree) used to spatially index the stations, as in
+/// [`SpatialCache`], which can likewise be constructed from projected coordinates with
+/// [`from_projected`](SpatioTemporalCache::from_projected). The series must all be aligned, i.e.
+/// have the same `start_time`, `period`, number of values, and number of leading and trailing
+/// points, so that the elements at a given index in each series together form a spatial slice.
+///
+/// Values are `f32` by default, but can be any [`Float`].
+#[derive(Debug, Clone)]
+pub struct SpatioTemporalCache<T = f32> {
+    /// an [R*-tree](https://en.wikipedia.org/wiki/R*-tree) used to spatially
+    /// index the stations
+    pub(crate) rtree: SpatialTree,
+    /// Timeseries for each station
+    pub(crate) series: Vec<SeriesCache<T>>,
+}
+
+impl<T: Float> SpatioTemporalCache<T> {
+    /// Create a new SpatioTemporalCache without manually constructing the R*-tree
+    ///
+    /// The latitude, longitude (in degrees), elevation (in meters) and series of each station
+    /// are given by the elements at the same index in each vector.
+    ///
+    /// ## Errors
+    ///
+    /// - the vectors do not all have the same length
+    /// - a latitude is not between -90 and 90, or a longitude is not between -360 and 360
+    /// - the series are not aligned
+    pub fn new(
+        lats: Vec<f32>,
+        lons: Vec<f32>,
+        elevs: Vec<f32>,
+        series: Vec<SeriesCache<T>>,
+    ) -> Result<Self, Error> {
+        Self::check_shape(&lats, &lons, &elevs, &series)?;
+        check_latlons(&lats, &lons)?;
+
+        Ok(Self {
+            rtree: SpatialTree::from_latlons(lats, lons, elevs),
+            series,
+        })
+    }
+
+    /// Create a new SpatioTemporalCache from station positions in a projected coordinate system
+    ///
+    /// As in [`new`](SpatioTemporalCache::new), but with the northing and easting (in meters) of
+    /// each station in place of its latitude and longitude. As with
+    /// [`SpatialCache::from_projected`], positions are only resolved to about half a meter at
+    /// typical northings.
+    ///
+    /// ## Errors
+    ///
+    /// - the vectors do not all have the same length
+    /// - the series are not aligned
+    pub fn from_projected(
+        northings: Vec<f32>,
+        eastings: Vec<f32>,
+        elevs: Vec<f32>,
+        series: Vec<SeriesCache<T>>,
+    ) -> Result<Self, Error> {
+        Self::check_shape(&northings, &eastings, &elevs, &series)?;
+
+        Ok(Self {
+            rtree: SpatialTree::from_projected(northings, eastings, elevs),
+            series,
+        })
+    }
+
+    fn check_shape(
+        lats: &[f32],
+        lons: &[f32],
+        elevs: &[f32],
+        series: &[SeriesCache<T>],
+    ) -> Result<(), Error> {
+        if lons.len() != lats.len() {
+            return Err(Error::InvalidInputShape("lons".to_string()));
+        }
+        if elevs.len() != lats.len() {
+            return Err(Error::InvalidInputShape("elevs".to_string()));
+        }
+        if series.len() != lats.len() {
+            return Err(Error::InvalidInputShape("series".to_string()));
+        }
+        check_aligned(&series.iter().collect::<Vec<_>>())
+    }
+
+    /// Get a reference to the series held inside the SpatioTemporalCache
+    pub fn series(&self) -> &[SeriesCache<T>] {
+        &self.series
+    }
+}
+
+/// check that latitudes and longitudes are within their valid ranges
+///
+/// Longitudes are checked against 360, not 180, as conventions of 0 to 360 and -360 to 0 are
+/// also in use.
+fn check_latlons(lats: &[f32], lons: &[f32]) -> Result<(), Error> {
+    // written so that NaNs fail too
+    if !lats.iter().all(|lat| lat.abs() <= 90.) {
+        return Err(Error::InvalidArg(
+            "lats".to_string(),
+            "must be between -90 and 90".to_string(),
+        ));
+    }
+    if !lons.iter().all(|lon| lon.abs() <= 360.) {
+        return Err(Error::InvalidArg(
+            "lons".to_string(),
+            "must be between -360 and 360".to_string(),
+        ));
+    }
+    Ok(())
 }
 
-pub(crate) const RADIUS_EARTH: f32 = 6371.0;
+/// mean radius of the earth in m
+pub(crate) const RADIUS_EARTH: f32 = 6371000.;
+
+pub(crate) fn is_valid<T: num_traits::Float>(value: T) -> bool {
+    !value.is_nan() && !value.is_infinite()
+}
 
-pub(crate) fn is_valid(value: f32) -> bool {
-    !f32::is_nan(value) && !f32::is_infinite(value)
+/// find the median of a non-empty slice, reordering it in the process
+pub(crate) fn median<T: Float>(values: &mut [T]) -> T {
+    let len = values.len();
+    let (lower, upper, _) = values.select_nth_unstable_by(len / 2, |a, b| {
+        a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    if len % 2 == 1 {
+        *upper
+    } else {
+        // the largest value below the middle
+        let below = lower.iter().copied().fold(T::neg_infinity(), T::max);
+        (below + *upper) / T::from_f32(2.)
+    }
 }
 
-/// convert lat-lon to xyz coordinates
+/// convert lat-lon to xyz coordinates in m
 pub(crate) fn convert_coordinates(lat: f32, lon: f32) -> (f32, f32, f32) {
     (
         lat.to_radians().cos() * lon.to_radians().cos() * RADIUS_EARTH,
@@ -106,36 +588,157 @@ pub(crate) fn convert_coordinates(lat: f32, lon: f32) -> (f32, f32, f32) {
     )
 }
 
-/// find the distance in km between two lat-lon points
-pub(crate) fn calc_distance(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> Result<f32, Error> {
-    // lons are checked against 360 here, not 180, because some people apparently use
-    // conventions of 0-360 and -360-0...
-    if lat1.abs() > 90. || lat2.abs() > 90. || lon1.abs() > 360. || lon2.abs() > 360. {
-        return Err(Error::InvalidArg(
-            "latlon".to_string(),
-            "outside valid range".to_string(),
-        ));
+/// find the great-circle distance in m between two lat-lon points
+pub(crate) fn great_circle_distance(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f32 {
+    // the haversine formula, in f64 as it is poorly conditioned for nearby points in f32
+    let (lat1, lon1) = (f64::from(lat1).to_radians(), f64::from(lon1).to_radians());
+    let (lat2, lon2) = (f64::from(lat2).to_radians(), f64::from(lon2).to_radians());
+
+    let haversine = ((lat2 - lat1) / 2.).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.).sin().powi(2);
+
+    (2. * haversine.sqrt().min(1.).asin() * f64::from(RADIUS_EARTH)) as f32
+}
+
+/// check that a set of timeseries are aligned, i.e. that their elements correspond one-to-one
+pub(crate) fn check_aligned<T>(series: &[&SeriesCache<T>]) -> Result<(), Error> {
+    if let Some((first, rest)) = series.split_first() {
+        for other in rest {
+            if other.start_time != first.start_time {
+                return Err(Error::InvalidArg(
+                    "start_time".to_string(),
+                    "must match across all series".to_string(),
+                ));
+            }
+            if other.period != first.period {
+                return Err(Error::InvalidArg(
+                    "period".to_string(),
+                    "must match across all series".to_string(),
+                ));
+            }
+            if other.values.len() != first.values.len()
+                || other.num_leading_points != first.num_leading_points
+                || other.num_trailing_points != first.num_trailing_points
+            {
+                return Err(Error::InvalidInputShape("values".to_string()));
+            }
+        }
     }
-    if lat1 == lat2 && lon1 == lon2 {
-        return Ok(0.);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_observations() {
+        let period = RelativeDuration::months(1);
+        // the 31st of January, March and May 2024
+        let (jan, mar, may) = (
+            Timestamp(1706659200),
+            Timestamp(1711843200),
+            Timestamp(1717113600),
+        );
+
+        let series =
+            SeriesCache::from_observations(&[(may, 3.), (jan, 1.)], period, mar, may, 2, 0)
+                .unwrap();
+        assert_eq!(series.start_time, jan);
+        assert_eq!(series.values, [Some(1.), None, None, None, Some(3.)]);
+
+        assert!(matches!(
+            SeriesCache::from_observations(&[(mar, 1.), (mar, 2.)], period, jan, may, 0, 0),
+            Err(Error::DuplicateTimestamp(time)) if time == mar
+        ));
+        assert!(matches!(
+            SeriesCache::from_observations(&[(Timestamp(mar.0 + 60), 1.)], period, jan, may, 0, 0),
+            Err(Error::OffGridTimestamp(_))
+        ));
     }
-    let lat1r = lat1.to_radians();
-    let lat2r = lat2.to_radians();
-    let lon1r = lon1.to_radians();
-    let lon2r = lon2.to_radians();
 
-    let ratio = lat1r.cos() * lon1r.cos() * lat2r.cos() * lon2r.cos()
-        + lat1r.cos() * lon1r.sin() * lat2r.cos() * lon2r.sin()
-        + lat1r.sin() * lat2r.sin();
+    #[test]
+    fn test_neighbour_queries() {
+        // along the equator, where a degree is about 111km
+        let data = SpatialCache::new(
+            vec![0.; 5],
+            vec![0., 0.01, 0.1, 1., -0.2],
+            vec![0., 100., 0., 0., 300.],
+            vec![0.; 5],
+        )
+        .unwrap();
+        let indices =
+            |neighbours: Vec<Neighbour>| neighbours.iter().map(|n| n.index).collect::<Vec<_>>();
 
-    // floating point chaos was leading to this leaking outside the 0-1 range that's
-    // valid for arccos, hence the enforcement
-    let norm_ratio = ratio.clamp(0., 1.);
+        let neighbours = data.neighbours_within(0., 0., 25000.);
+        assert_eq!(indices(neighbours.clone()), [0, 1, 2, 4]);
+        assert_eq!(neighbours[0].distance, 0.);
+        assert!((neighbours[1].distance - 1111.95).abs() < 0.1);
+        assert_eq!(indices(data.neighbours_within(0., 0., 1000.)), [0]);
+        assert_eq!(
+            indices(data.neighbours_within(0., 0., 200000.)),
+            [0, 1, 2, 4, 3]
+        );
 
-    Ok(norm_ratio.acos() * RADIUS_EARTH)
-}
+        assert_eq!(
+            indices(data.nearest_neighbours(0., 0., 2, f32::INFINITY)),
+            [0, 1]
+        );
+        assert_eq!(
+            indices(data.nearest_neighbours(0., 1., 3, f32::INFINITY)),
+            [3, 2, 1]
+        );
+        assert_eq!(indices(data.nearest_neighbours(0., 1., 3, 105000.)), [3, 2]);
+
+        assert_eq!(
+            indices(data.neighbours_within_elev_band(0., 0., 0., 25000., 100.)),
+            [0, 1, 2]
+        );
+
+        assert_eq!(data.coords(1), Some((0., 0.01, 100.)));
+        assert_eq!(data.coords(5), None);
+    }
 
-/// find the distance in km between two xyz points
-pub(crate) fn calc_distance_xyz(x0: f32, y0: f32, z0: f32, x1: f32, y1: f32, z1: f32) -> f32 {
-    ((x0 - x1) * (x0 - x1) + (y0 - y1) * (y0 - y1) + (z0 - z1) * (z0 - z1)).sqrt()
+    #[test]
+    fn test_spatial_cache_validation() {
+        let new = |lats: Vec<f32>, lons: Vec<f32>, elevs: Vec<f32>| {
+            SpatialCache::new(lats, lons, elevs, vec![0.; 2])
+        };
+
+        assert!(new(vec![60., 60.], vec![10., 350.], vec![0., 0.]).is_ok());
+        assert!(matches!(
+            new(vec![60., 60.], vec![10.], vec![0., 0.]),
+            Err(Error::InvalidInputShape(name)) if name == "lons"
+        ));
+        assert!(matches!(
+            new(vec![60., 60.], vec![10., 10.], vec![0.]),
+            Err(Error::InvalidInputShape(name)) if name == "elevs"
+        ));
+        assert!(matches!(
+            SpatialCache::new(vec![60.], vec![10.], vec![0.], vec![0.; 2]),
+            Err(Error::InvalidInputShape(name)) if name == "values"
+        ));
+        assert!(matches!(
+            new(vec![60., 91.], vec![10., 10.], vec![0., 0.]),
+            Err(Error::InvalidArg(name, _)) if name == "lats"
+        ));
+        assert!(matches!(
+            new(vec![60., 60.], vec![10., f32::NAN], vec![0., 0.]),
+            Err(Error::InvalidArg(name, _)) if name == "lons"
+        ));
+
+        // projected coordinates aren't range checked
+        assert!(SpatialCache::from_projected(
+            vec![6.7e6; 2],
+            vec![5e5; 2],
+            vec![0.; 2],
+            vec![0.; 2]
+        )
+        .is_ok());
+        assert!(matches!(
+            SpatialCache::from_projected(vec![6.7e6; 2], vec![5e5; 2], vec![0.; 3], vec![0.; 2]),
+            Err(Error::InvalidInputShape(name)) if name == "elevs"
+        ));
+    }
 }
@@ -0,0 +1,119 @@
+use crate::{Error, Flag};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Run a closure with QC tests using `num_threads` threads
+///
+/// With the `parallel` feature enabled, spatial tests such as [`sct`](crate::sct) and
+/// [`buddy_check`](crate::buddy_check), as well as [`batch_check`](crate::batch_check), run on
+/// rayon's global thread pool, which by default has one thread per CPU. Tests called inside `f`
+/// instead run on a pool of `num_threads` threads. With 1 thread, spatial tests are run serially.
+///
+/// ## Errors
+///
+/// - the thread pool could not be created
+#[cfg(feature = "parallel")]
+pub fn with_num_threads<R, F>(num_threads: usize, f: F) -> Result<R, Error>
+where
+    R: Send,
+    F: FnOnce() -> R + Send,
+{
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()
+        .map_err(|e| Error::InvalidArg("num_threads".to_string(), e.to_string()))?;
+
+    Ok(pool.install(f))
+}
+
+/// number of updates to compute concurrently
+fn chunk_size() -> usize {
+    #[cfg(feature = "parallel")]
+    match rayon::current_num_threads() {
+        1 => 1,
+        n => n * 4,
+    }
+    #[cfg(not(feature = "parallel"))]
+    1
+}
+
+/// Visit each index in `0..len` in order, applying an update computed from the flags to each
+/// candidate, giving the same results as doing so serially, but computing the updates in parallel
+/// with the `parallel` feature.
+///
+/// `compute` returns the update for an index, along with the indices of the flags it read. Updates
+/// are computed speculatively in chunks from the same flags, then applied in order by `apply`. If
+/// a flag read by an update was changed by an earlier update in the chunk, it is recomputed before
+/// being applied. Likewise, if an index stops being a candidate because of an earlier update, it
+/// is skipped. For this to be correct, indices must never become candidates because of an update,
+/// and `compute` must read nothing that `apply` changes, except the flags it reports.
+///
+/// `conflicts(i, j)` should return true if the update for `i` is likely to stop `j` being a
+/// candidate, or to change flags that `j` reads. Such a `j` is not computed speculatively in the
+/// same chunk as `i`, where the work would likely be thrown away, but when it is reached, if it
+/// is still a candidate then.
+pub(crate) fn apply_in_order<E, T, C, D, F, A>(
+    len: usize,
+    flags: &mut [Flag],
+    state: &mut E,
+    is_candidate: C,
+    conflicts: D,
+    compute: F,
+    mut apply: A,
+) -> Result<(), Error>
+where
+    T: Send,
+    C: Fn(&[Flag], &E, usize) -> bool,
+    D: Fn(usize, usize) -> bool,
+    F: Fn(&[Flag], usize) -> Result<(T, Vec<usize>), Error> + Sync,
+    A: FnMut(&mut [Flag], &mut E, usize, T),
+{
+    let chunk_size = chunk_size();
+    let mut next = 0;
+
+    while next < len {
+        let start = next;
+        let mut chunk: Vec<usize> = Vec::with_capacity(chunk_size);
+        while next < len && chunk.len() < chunk_size {
+            if is_candidate(flags, state, next) && !chunk.iter().any(|i| conflicts(*i, next)) {
+                chunk.push(next);
+            }
+            next += 1;
+        }
+
+        let speculate = |i: &usize| {
+            compute(flags, *i).map(|(update, reads)| {
+                let seen: Vec<(usize, Flag)> = reads.into_iter().map(|j| (j, flags[j])).collect();
+                (update, seen)
+            })
+        };
+        #[cfg(feature = "parallel")]
+        let speculations: Vec<_> = chunk.par_iter().map(speculate).collect();
+        #[cfg(not(feature = "parallel"))]
+        let speculations: Vec<_> = chunk.iter().map(speculate).collect();
+
+        // indices skipped for conflicts are in between those in the chunk, and are computed here
+        let mut speculations = chunk.into_iter().zip(speculations).peekable();
+        for i in start..next {
+            let speculation = speculations
+                .next_if(|(j, _)| *j == i)
+                .map(|(_, speculation)| speculation);
+            if !is_candidate(flags, state, i) {
+                continue;
+            }
+
+            let update = match speculation {
+                Some(Ok((update, seen))) if seen.iter().all(|(j, flag)| flags[*j] == *flag) => {
+                    update
+                }
+                // either the flags changed, there was an error that might not have happened with
+                // the current flags, or it wasn't computed speculatively
+                _ => compute(flags, i)?.0,
+            };
+
+            apply(flags, state, i, update);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use crate::{util, Aggregation, Error, Float, SeriesCache, Timestamp};
+use chronoutil::RelativeDuration;
+
+impl<T: Float> SeriesCache<T> {
+    /// Resample the series to a new period
+    ///
+    /// The resampled series starts at `start_time`, with elements every `period`. Each element at
+    /// time `t` aggregates the valid values in this series with timestamps in the interval
+    /// (`t - period`, `t`], according to `aggregation`. If fewer than `min_valid` values in the
+    /// interval are valid (or none are), the element is `None`. This is the same convention as
+    /// used by [`aggregate_check`](crate::aggregate_check), e.g. resampling hourly data to a
+    /// `start_time` of 06:00 and a `period` of 1 day gives totals from 07:00 the previous day, up
+    /// to and including 06:00.
+    ///
+    /// The resampled series runs until the last interval that overlaps this series. Month
+    /// arithmetic in either period is respected, so daily data can be resampled to monthly.
+    ///
+    /// The returned series has no leading or trailing points.
+    ///
+    /// ## Errors
+    ///
+    /// - either period is not positive
+    /// - the timestamps involved are outside the range that can be represented
+    pub fn resample(
+        &self,
+        start_time: Timestamp,
+        period: RelativeDuration,
+        aggregation: Aggregation,
+        min_valid: usize,
+    ) -> Result<SeriesCache<T>, Error> {
+        let mut values = Vec::new();
+
+        if let Some(last) = self.values.len().checked_sub(1) {
+            let last_time = self.time_at(last as i64)?;
+
+            for k in 0.. {
+                let interval_end = start_time.add_periods(period, k)?;
+                let interval_start = start_time.add_periods(period, k - 1)?;
+                if interval_start >= interval_end {
+                    return Err(Error::InvalidArg(
+                        "period".to_string(),
+                        "must be positive".to_string(),
+                    ));
+                }
+                if interval_start >= last_time {
+                    break;
+                }
+
+                let first = util::first_index_after(self.start_time, self.period, interval_start)?
+                    .clamp(0, self.values.len() as i64) as usize;
+                let end = util::first_index_after(self.start_time, self.period, interval_end)?
+                    .clamp(0, self.values.len() as i64) as usize;
+
+                let valid: Vec<T> = self.values[first..end].iter().flatten().copied().collect();
+
+                values.push(
+                    (!valid.is_empty() && valid.len() >= min_valid)
+                        .then(|| aggregation.apply(&valid)),
+                );
+            }
+        }
+
+        Ok(SeriesCache {
+            start_time,
+            period,
+            values,
+            num_leading_points: 0,
+            num_trailing_points: 0,
+        })
+    }
+
+    /// Align the series to a new start time, keeping the same period and end time
+    ///
+    /// If `start_time` is before the current start time, the series is padded at the start with
+    /// `None`s, if it is after, values before it are dropped. `start_time` must lie on the grid of
+    /// times defined by the series' current `start_time` and `period`, to shift the series onto a
+    /// different grid, use [`resample`](SeriesCache::resample).
+    ///
+    /// The returned series has no leading or trailing points.
+    ///
+    /// ## Errors
+    ///
+    /// - `start_time` is not on the series' grid
+    /// - the period is not positive
+    /// - the timestamps involved are outside the range that can be represented
+    pub fn align(&self, start_time: Timestamp) -> Result<SeriesCache<T>, Error> {
+        let offset = util::first_index_after(self.start_time, self.period, start_time)? - 1;
+        if self.time_at(offset)? != start_time {
+            return Err(Error::InvalidArg(
+                "start_time".to_string(),
+                "not on the series' grid".to_string(),
+            ));
+        }
+
+        let values = if offset < 0 {
+            std::iter::repeat_n(None, offset.unsigned_abs() as usize)
+                .chain(self.values.iter().copied())
+                .collect()
+        } else {
+            self.values
+                .get(offset as usize..)
+                .unwrap_or_default()
+                .to_vec()
+        };
+
+        Ok(SeriesCache {
+            start_time,
+            period: self.period,
+            values,
+            num_leading_points: 0,
+            num_trailing_points: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resample() {
+        // daily from the 1st of January 2024
+        let daily = SeriesCache {
+            start_time: Timestamp(1704067200),
+            period: RelativeDuration::days(1),
+            values: (0..70)
+                .map(|day| if day % 10 == 0 { None } else { Some(1.) })
+                .collect(),
+            num_leading_points: 0,
+            num_trailing_points: 0,
+        };
+
+        // monthly totals, labelled by the end of the month
+        let monthly = daily
+            .resample(
+                Timestamp(1706659200),
+                RelativeDuration::months(1),
+                Aggregation::Sum,
+                25,
+            )
+            .unwrap();
+        assert_eq!(monthly.values, [Some(27.), Some(27.), None]);
+
+        let aligned = daily.align(Timestamp(1704067200 - 2 * 86400)).unwrap();
+        assert_eq!(aligned.values.len(), 72);
+        assert_eq!(aligned.values[..3], [None, None, None]);
+        assert_eq!(aligned.values[3], Some(1.));
+
+        let aligned = daily.align(Timestamp(1704067200 + 68 * 86400)).unwrap();
+        assert_eq!(aligned.values, [Some(1.), Some(1.)]);
+
+        assert!(daily.align(Timestamp(1704067200 + 3600)).is_err());
+    }
+}
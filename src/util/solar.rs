@@ -0,0 +1,86 @@
+use crate::{Error, Timestamp};
+use chrono::{Datelike, Timelike};
+use std::f64::consts::PI;
+
+/// Solar constant, in W/m²
+const SOLAR_CONSTANT: f64 = 1361.;
+
+/// Angle of the earth around its orbit at `time`, in radians, along with the UTC time of day in
+/// minutes
+fn fractional_year(time: Timestamp) -> Result<(f64, f64), Error> {
+    let datetime = time.to_datetime()?;
+    let minutes =
+        (datetime.hour() * 60 + datetime.minute()) as f64 + datetime.second() as f64 / 60.;
+    let days_in_year = if datetime.date_naive().leap_year() {
+        366.
+    } else {
+        365.
+    };
+
+    Ok((
+        2. * PI / days_in_year * (datetime.ordinal0() as f64 + (minutes / 60. - 12.) / 24.),
+        minutes,
+    ))
+}
+
+/// Compute the elevation of the sun above the horizon, in degrees
+///
+/// `lat` and `lon` are in degrees, with longitudes outside \[-180, 180\] wrapped into it. The
+/// sun's position is computed with the NOAA approximations for the equation of time and
+/// declination, which are accurate to within a few tenths of a degree. Refraction is not
+/// accounted for.
+///
+/// ```
+/// use olympian::{solar_elevation, Timestamp};
+///
+/// // Oslo, around solar noon on the summer solstice of 2024
+/// let elevation = solar_elevation(59.91, 10.75, Timestamp(1718968620)).unwrap();
+/// assert!((elevation - 53.5).abs() < 0.5);
+/// ```
+///
+/// ## Errors
+///
+/// - `time` is outside the range that can be represented
+pub fn solar_elevation(lat: f32, lon: f32, time: Timestamp) -> Result<f32, Error> {
+    let (gamma, minutes) = fractional_year(time)?;
+
+    // in minutes
+    let equation_of_time = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2. * gamma).cos()
+            - 0.040849 * (2. * gamma).sin());
+    // in radians
+    let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2. * gamma).cos()
+        + 0.000907 * (2. * gamma).sin()
+        - 0.002697 * (3. * gamma).cos()
+        + 0.00148 * (3. * gamma).sin();
+
+    let lon = (lon as f64 + 180.).rem_euclid(360.) - 180.;
+    let true_solar_time = minutes + equation_of_time + 4. * lon;
+    let hour_angle = (true_solar_time / 4. - 180.).to_radians();
+    let lat = (lat as f64).to_radians();
+
+    let sin_elevation =
+        lat.sin() * declination.sin() + lat.cos() * declination.cos() * hour_angle.cos();
+
+    Ok(sin_elevation.clamp(-1., 1.).asin().to_degrees() as f32)
+}
+
+/// Compute the extraterrestrial irradiance on a horizontal surface, in W/m², given the sun's
+/// elevation in degrees
+///
+/// This is 0 when the sun is below the horizon.
+pub(crate) fn extraterrestrial_irradiance(elevation: f32, time: Timestamp) -> Result<f32, Error> {
+    let (gamma, _) = fractional_year(time)?;
+
+    // correction for the eccentricity of the earth's orbit
+    let eccentricity = 1.00011
+        + 0.034221 * gamma.cos()
+        + 0.00128 * gamma.sin()
+        + 0.000719 * (2. * gamma).cos()
+        + 0.000077 * (2. * gamma).sin();
+
+    Ok((SOLAR_CONSTANT * eccentricity * (elevation as f64).to_radians().sin()).max(0.) as f32)
+}
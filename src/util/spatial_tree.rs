@@ -1,4 +1,4 @@
-use crate::util;
+use crate::util::{self, Neighbour, RADIUS_EARTH};
 use rstar::{primitives::GeomWithData, RTree};
 
 /// A point in the [`SpatialTree`]
@@ -8,15 +8,27 @@ use rstar::{primitives::GeomWithData, RTree};
 /// elevs, and values arrays associated with that point.
 pub(crate) type SpatialPoint = GeomWithData<[f32; 3], usize>;
 
+/// How the horizontal positions of the points in a [`SpatialTree`] are given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Geometry {
+    /// Latitude and longitude in degrees, on a spherical earth
+    Spherical,
+    /// Northing and easting in meters, in a projected coordinate system
+    Planar,
+}
+
 /// An R-tree to spatially index data to spatially index data
 ///
 /// This allows a data point's nearest neighbours to be found with ease
 #[derive(Debug, Clone)]
 pub(crate) struct SpatialTree {
     pub(crate) tree: RTree<SpatialPoint>,
+    /// latitudes, or northings with [`Geometry::Planar`]
     pub(crate) lats: Vec<f32>,
+    /// longitudes, or eastings with [`Geometry::Planar`]
     pub(crate) lons: Vec<f32>,
     pub(crate) elevs: Vec<f32>,
+    pub(crate) geometry: Geometry,
 }
 
 impl SpatialTree {
@@ -26,16 +38,25 @@ impl SpatialTree {
     /// the elements from each vector at a given index together specify a
     /// single point in space
     pub(crate) fn from_latlons(lats: Vec<f32>, lons: Vec<f32>, elevs: Vec<f32>) -> Self {
+        Self::new(lats, lons, elevs, Geometry::Spherical)
+    }
+
+    /// Construct a SpatialTree from a set of positions in a projected coordinate system
+    ///
+    /// As in [`from_latlons`](SpatialTree::from_latlons), but with northings and eastings in
+    /// meters in place of lats and lons
+    pub(crate) fn from_projected(northings: Vec<f32>, eastings: Vec<f32>, elevs: Vec<f32>) -> Self {
+        Self::new(northings, eastings, elevs, Geometry::Planar)
+    }
+
+    fn new(lats: Vec<f32>, lons: Vec<f32>, elevs: Vec<f32>, geometry: Geometry) -> Self {
         //TODO: ensure vecs are the same size
 
         let raw_points: Vec<SpatialPoint> = lats
             .iter()
             .zip(lons.iter())
             .enumerate()
-            .map(|(i, (lat, lon))| {
-                let (x, y, z) = util::convert_coordinates(*lat, *lon);
-                SpatialPoint::new([x, y, z], i)
-            })
+            .map(|(i, (lat, lon))| SpatialPoint::new(geometry.xyz(*lat, *lon), i))
             .collect();
 
         let tree = RTree::bulk_load(raw_points);
@@ -45,52 +66,202 @@ impl SpatialTree {
             lats,
             lons,
             elevs,
+            geometry,
         }
     }
 
-    pub(crate) fn get_neighbours(
-        &self,
-        lat: f32,
-        lon: f32,
-        radius: f32,
-        include_match: bool,
-    ) -> Vec<&SpatialPoint> {
-        let (x, y, z) = util::convert_coordinates(lat, lon);
-
-        let match_iter = self.tree.locate_within_distance([x, y, z], radius);
+    /// Find the points within distance `radius` \[m\] of a location, ordered by distance
+    pub(crate) fn neighbours_within(&self, lat: f32, lon: f32, radius: f32) -> Vec<Neighbour> {
+        let mut neighbours: Vec<Neighbour> = self
+            .tree
+            .locate_within_distance(
+                self.geometry.xyz(lat, lon),
+                self.geometry.max_chord_2(radius),
+            )
+            .filter_map(|point| self.neighbour(lat, lon, point.data, radius))
+            .collect();
+        sort_by_distance(&mut neighbours);
 
-        match include_match {
-            true => match_iter.collect(),
-            false => match_iter
-                .filter(|point| *point.geom() != [x, y, z])
-                .collect(),
-        }
+        neighbours
     }
 
-    pub(crate) fn get_neighbours_with_distance(
+    /// Find up to `num_max` of the nearest points within distance `radius` \[m\] of a location,
+    /// ordered by distance
+    pub(crate) fn nearest_neighbours(
         &self,
         lat: f32,
         lon: f32,
+        num_max: usize,
         radius: f32,
-        include_match: bool,
-    ) -> (Vec<&SpatialPoint>, Vec<f32>) {
-        let points = self.get_neighbours(lat, lon, radius, include_match);
-        let vec_length = points.len();
+    ) -> Vec<Neighbour> {
+        let max_chord_2 = self.geometry.max_chord_2(radius);
 
-        let mut distances = vec![0.; vec_length];
+        // chord length increases with distance, so the points come in order
+        let mut neighbours: Vec<Neighbour> = self
+            .tree
+            .nearest_neighbor_iter_with_distance_2(&self.geometry.xyz(lat, lon))
+            .take_while(|(_, chord_2)| *chord_2 <= max_chord_2)
+            .filter_map(|(point, _)| self.neighbour(lat, lon, point.data, radius))
+            .take(num_max)
+            .collect();
+        // but rounding can swap points at almost the same distance
+        sort_by_distance(&mut neighbours);
 
-        let (x, y, z) = util::convert_coordinates(lat, lon);
+        neighbours
+    }
 
-        for i in 0..vec_length {
-            let (x1, y1, z1) =
-                util::convert_coordinates(self.lats[points[i].data], self.lons[points[i].data]);
-            distances[i] = util::calc_distance_xyz(x, y, z, x1, y1, z1)
-        }
+    /// the point at `index` as a neighbour of a location, if it is within `radius` \[m\]
+    fn neighbour(&self, lat: f32, lon: f32, index: usize, radius: f32) -> Option<Neighbour> {
+        let distance = self
+            .geometry
+            .distance(lat, lon, self.lats[index], self.lons[index]);
 
-        (points, distances)
+        (distance <= radius).then_some(Neighbour { index, distance })
     }
 
     pub(crate) fn get_coords_at_index(&self, i: usize) -> (f32, f32, f32) {
         (self.lats[i], self.lons[i], self.elevs[i])
     }
 }
+
+impl Geometry {
+    /// the xyz coordinates \[m\] used to index a position in the tree
+    fn xyz(&self, lat: f32, lon: f32) -> [f32; 3] {
+        match self {
+            Geometry::Spherical => {
+                let (x, y, z) = util::convert_coordinates(lat, lon);
+                [x, y, z]
+            }
+            Geometry::Planar => [lon, lat, 0.],
+        }
+    }
+
+    /// the distance \[m\] between two positions, great-circle for [`Geometry::Spherical`]
+    pub(crate) fn distance(&self, lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f32 {
+        match self {
+            Geometry::Spherical => util::great_circle_distance(lat1, lon1, lat2, lon2),
+            Geometry::Planar => {
+                (f64::from(lat1) - f64::from(lat2)).hypot(f64::from(lon1) - f64::from(lon2)) as f32
+            }
+        }
+    }
+
+    /// the squared length of the chord in the tree's xyz coordinates subtended by an arc of
+    /// length `radius`, padded a little so no points are missed due to rounding
+    ///
+    /// As the tree's coordinates are not precise enough to tell distances apart, points within
+    /// this are then filtered by their actual distance.
+    fn max_chord_2(&self, radius: f32) -> f32 {
+        let chord = match self {
+            Geometry::Spherical => {
+                let angle = radius / RADIUS_EARTH;
+                if angle >= std::f32::consts::PI {
+                    return f32::INFINITY;
+                }
+
+                2. * RADIUS_EARTH * (angle / 2.).sin()
+            }
+            Geometry::Planar => radius,
+        };
+
+        // f32 coordinates on the scale of the earth are only precise to within a metre or so
+        (chord * 1.001 + 1.).powi(2)
+    }
+}
+
+/// sort neighbours by distance, breaking ties by index so the order doesn't depend on the tree
+fn sort_by_distance(neighbours: &mut [Neighbour]) {
+    neighbours.sort_by(|a, b| {
+        a.distance
+            .partial_cmp(&b.distance)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.index.cmp(&b.index))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(neighbours: &[Neighbour]) -> Vec<usize> {
+        neighbours.iter().map(|neighbour| neighbour.index).collect()
+    }
+
+    #[test]
+    fn test_small_radii() {
+        // points 1m, 10m, 100m and 50km north of the first, a degree of latitude being about 111km
+        let tree = SpatialTree::from_latlons(
+            vec![60., 60.000009, 60.00009, 60.0009, 60.45],
+            vec![10.; 5],
+            vec![0.; 5],
+        );
+
+        let neighbours = tree.neighbours_within(60., 10., 5.);
+        assert_eq!(indices(&neighbours), [0, 1]);
+        // to within the precision of f32 latitudes
+        assert!((neighbours[1].distance - 1.).abs() < 0.25);
+        assert_eq!(indices(&tree.neighbours_within(60., 10., 50.)), [0, 1, 2]);
+        assert_eq!(
+            indices(&tree.neighbours_within(60., 10., 150.)),
+            [0, 1, 2, 3]
+        );
+        // a radius in m used to be taken as a squared distance in km, which included this
+        assert_eq!(
+            indices(&tree.neighbours_within(60., 10., 10000.)),
+            [0, 1, 2, 3]
+        );
+
+        assert_eq!(
+            indices(&tree.nearest_neighbours(60., 10., 3, 10000.)),
+            [0, 1, 2]
+        );
+        assert_eq!(
+            indices(&tree.nearest_neighbours(60.45, 10., 3, 10000.)),
+            [4]
+        );
+        assert!(tree.neighbours_within(60., 10., -1.).is_empty());
+    }
+
+    #[test]
+    fn test_large_radii() {
+        // points a quarter and half way around the equator, which is about 40000km long
+        let tree = SpatialTree::from_latlons(vec![0.; 3], vec![0., 90., 180.], vec![0.; 3]);
+
+        let neighbours = tree.neighbours_within(0., 0., 10010000.);
+        assert_eq!(indices(&neighbours), [0, 1]);
+        // great-circle, not chord, distance
+        assert!((neighbours[1].distance - 10007543.).abs() < 10.);
+        assert!((tree.neighbours_within(0., 0., 20020000.)[2].distance - 20015086.).abs() < 10.);
+        assert_eq!(indices(&tree.neighbours_within(0., 0., 20000000.)), [0, 1]);
+        assert_eq!(
+            indices(&tree.neighbours_within(0., 0., f32::INFINITY)),
+            [0, 1, 2]
+        );
+
+        assert_eq!(
+            indices(&tree.nearest_neighbours(0., 170., 2, f32::INFINITY)),
+            [2, 1]
+        );
+    }
+
+    #[test]
+    fn test_projected() {
+        // on a grid with 1km spacing, far from the origin
+        let tree = SpatialTree::from_projected(
+            vec![6500000., 6500000., 6501000., 6501000.],
+            vec![300000., 301000., 300000., 301000.],
+            vec![0.; 4],
+        );
+
+        let neighbours = tree.neighbours_within(6500000., 300000., 1200.);
+        assert_eq!(indices(&neighbours), [0, 1, 2]);
+        assert_eq!(neighbours[1].distance, 1000.);
+        assert!(
+            (tree.neighbours_within(6500000., 300000., 1500.)[3].distance - 1414.21).abs() < 0.01
+        );
+        assert_eq!(
+            indices(&tree.nearest_neighbours(6501000., 301000., 1, f32::INFINITY)),
+            [3]
+        );
+    }
+}
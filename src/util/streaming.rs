@@ -0,0 +1,330 @@
+use crate::{dip_check, step_check, Error, Flag, Float, SeriesCache, Timestamp};
+use chronoutil::RelativeDuration;
+use std::collections::VecDeque;
+
+/// Series for QCing data incrementally as it arrives in real time
+///
+/// Values are pushed one period at a time into a ring buffer holding the most recent `capacity`
+/// values. After pushing, a timeseries QC test can be run on just the values that have become
+/// QCable since it was last run, without rebuilding a [`SeriesCache`] or re-running the test over
+/// the whole window. A value is QCable once the values it needs as context have been pushed, so
+/// for example [`step_check`](StreamingSeries::step_check) can QC a value as soon as it has been
+/// pushed, while [`dip_check`](StreamingSeries::dip_check) needs a successor, so it QCs each
+/// value after the next one has been pushed.
+///
+/// The series keeps track of which values have been QCed, so every value pushed is flagged
+/// exactly once, however many values are pushed between runs of the test. Values whose preceding
+/// context was never pushed, or has already left the buffer by the time the test is run, are
+/// flagged DataMissing, so the capacity should allow for the number of values that may be pushed
+/// between runs, on top of the context the test needs. As the series keeps a single record of
+/// which values have been QCed, it should only be used to run one test, to run several, keep a
+/// StreamingSeries for each.
+///
+/// Values are `f32` by default, but can be any [`Float`].
+///
+/// ```
+/// use chronoutil::RelativeDuration;
+/// use olympian::{Flag, StreamingSeries, Timestamp};
+///
+/// let mut series = StreamingSeries::new(Timestamp(0), RelativeDuration::hours(1), 24).unwrap();
+///
+/// series.push(Some(1.)).unwrap();
+/// // the first value has no predecessor to compare against
+/// assert_eq!(
+///     series.step_check(3., 5.).unwrap(),
+///     [(Timestamp(0), Flag::DataMissing)]
+/// );
+///
+/// series.push(Some(5.)).unwrap();
+/// series.push(Some(5.5)).unwrap();
+/// // both values pushed since the test was last run are QCed
+/// assert_eq!(
+///     series.step_check(3., 5.).unwrap(),
+///     [(Timestamp(3600), Flag::Warn), (Timestamp(7200), Flag::Pass)]
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamingSeries<T = f32> {
+    /// Time of the first value ever pushed
+    origin: Timestamp,
+    period: RelativeDuration,
+    /// Number of values that have been dropped from the front of the buffer
+    num_dropped: i64,
+    /// Number of values, counted from the first ever pushed, that have been flagged
+    num_flagged: i64,
+    values: VecDeque<Option<T>>,
+    capacity: usize,
+}
+
+impl<T: Float> StreamingSeries<T> {
+    /// Create a new, empty, StreamingSeries
+    ///
+    /// `start_time` is the time of the first value that will be pushed, and `capacity` is the
+    /// number of values retained in the buffer.
+    ///
+    /// ## Errors
+    ///
+    /// - `capacity` is 0
+    pub fn new(
+        start_time: Timestamp,
+        period: RelativeDuration,
+        capacity: usize,
+    ) -> Result<Self, Error> {
+        if capacity == 0 {
+            return Err(Error::InvalidArg(
+                String::from("capacity"),
+                String::from("must be > 0"),
+            ));
+        }
+
+        Ok(Self {
+            origin: start_time,
+            period,
+            num_dropped: 0,
+            num_flagged: 0,
+            values: VecDeque::with_capacity(capacity),
+            capacity,
+        })
+    }
+
+    /// Push the value for the next period, returning its time
+    ///
+    /// `None` should be pushed for periods where the value is missing. If the buffer is full, the
+    /// oldest value is dropped.
+    ///
+    /// ## Errors
+    ///
+    /// - the time of the value is outside the range that can be represented
+    pub fn push(&mut self, value: Option<T>) -> Result<Timestamp, Error> {
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+            self.num_dropped += 1;
+        }
+        self.values.push_back(value);
+
+        self.time_at(self.values.len() - 1)
+    }
+
+    /// Get the time of the most recently pushed value, if any
+    pub fn latest_time(&self) -> Option<Timestamp> {
+        self.values
+            .len()
+            .checked_sub(1)
+            .and_then(|i| self.time_at(i).ok())
+    }
+
+    /// get the time of the value at index i in the buffer
+    fn time_at(&self, i: usize) -> Result<Timestamp, Error> {
+        self.time_of(self.num_dropped + i as i64)
+    }
+
+    /// get the time of the value at `index`, counted from the first value ever pushed
+    fn time_of(&self, index: i64) -> Result<Timestamp, Error> {
+        self.origin.add_periods(self.period, index)
+    }
+
+    /// Get a [`SeriesCache`] for QCing the newest value that has `num_leading_points` before it
+    /// and `num_trailing_points` after it in the buffer
+    ///
+    /// Returns `None` if there are not yet enough values in the buffer.
+    ///
+    /// ## Errors
+    ///
+    /// - the window needed is larger than the capacity of the buffer
+    pub fn window(
+        &self,
+        num_leading_points: u8,
+        num_trailing_points: u8,
+    ) -> Result<Option<SeriesCache<T>>, Error> {
+        let window_size = num_leading_points as usize + num_trailing_points as usize + 1;
+        if window_size > self.capacity {
+            return Err(Error::InvalidArg(
+                String::from("capacity"),
+                String::from("too small for the requested window"),
+            ));
+        }
+
+        let Some(first) = self.values.len().checked_sub(window_size) else {
+            return Ok(None);
+        };
+
+        Ok(Some(SeriesCache {
+            start_time: self.time_at(first)?,
+            period: self.period,
+            values: self.values.range(first..).copied().collect(),
+            num_leading_points,
+            num_trailing_points,
+        }))
+    }
+
+    /// Run a timeseries QC test on the values that have become QCable since it was last run
+    ///
+    /// A value is QCable once it has `num_trailing_points` after it in the buffer. `test` is
+    /// called once with a [`SeriesCache`] containing the values to QC and their context, and must
+    /// return one flag per value. Values that don't have `num_leading_points` before them in the
+    /// buffer, either because they were among the first pushed, or because their context has
+    /// already been dropped, are flagged DataMissing without calling `test`. Returns the time of
+    /// each value QCed along with its flag, in order, which is empty if no values have become
+    /// QCable.
+    ///
+    /// ## Errors
+    ///
+    /// - the window needed is larger than the capacity of the buffer
+    /// - `test` returned an error, or did not return one flag per value. The values are then
+    ///   left to be QCed by the next run
+    pub fn qc_pending<F>(
+        &mut self,
+        num_leading_points: u8,
+        num_trailing_points: u8,
+        test: F,
+    ) -> Result<Vec<(Timestamp, Flag)>, Error>
+    where
+        F: FnOnce(&SeriesCache<T>) -> Result<Vec<Flag>, Error>,
+    {
+        let window_size = num_leading_points as usize + num_trailing_points as usize + 1;
+        if window_size > self.capacity {
+            return Err(Error::InvalidArg(
+                String::from("capacity"),
+                String::from("too small for the requested window"),
+            ));
+        }
+
+        // indices here are counted from the first value ever pushed
+        let end = self.num_dropped + self.values.len() as i64 - num_trailing_points as i64;
+        let first_with_context = self.num_dropped + num_leading_points as i64;
+
+        let mut flags = Vec::new();
+        let mut next = self.num_flagged;
+        while next < end.min(first_with_context) {
+            flags.push((self.time_of(next)?, Flag::DataMissing));
+            next += 1;
+        }
+
+        if next < end {
+            let first = (next - num_leading_points as i64 - self.num_dropped) as usize;
+            let window = SeriesCache {
+                start_time: self.time_at(first)?,
+                period: self.period,
+                values: self.values.range(first..).copied().collect(),
+                num_leading_points,
+                num_trailing_points,
+            };
+
+            let window_flags = test(&window)?;
+            if window_flags.len() as i64 != end - next {
+                return Err(Error::InvalidInputShape(String::from("flags")));
+            }
+            for (k, flag) in window_flags.into_iter().enumerate() {
+                flags.push((self.time_of(next + k as i64)?, flag));
+            }
+            next = end;
+        }
+
+        self.num_flagged = next;
+        Ok(flags)
+    }
+
+    /// Run [`step_check`](crate::step_check) on the values pushed since it was last run
+    ///
+    /// See [`qc_pending`](StreamingSeries::qc_pending)
+    pub fn step_check(&mut self, high: T, max: T) -> Result<Vec<(Timestamp, Flag)>, Error> {
+        self.qc_pending(1, 0, |window| step_check(window, high, max))
+    }
+
+    /// Run [`dip_check`](crate::dip_check) on the values that have gained a successor since it
+    /// was last run
+    ///
+    /// See [`qc_pending`](StreamingSeries::qc_pending)
+    pub fn dip_check(&mut self, high: T, max: T) -> Result<Vec<(Timestamp, Flag)>, Error> {
+        self.qc_pending(1, 1, |window| dip_check(window, high, max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIP_VALUES: [f32; 6] = [1., 1., 6., 1., 1., 1.];
+
+    #[test]
+    fn test_streaming_series() {
+        let mut series = StreamingSeries::new(Timestamp(0), RelativeDuration::hours(1), 3).unwrap();
+
+        let mut dip_flags = Vec::new();
+        for (i, value) in DIP_VALUES.into_iter().enumerate() {
+            assert_eq!(
+                series.push(Some(value)).unwrap(),
+                Timestamp(i as i64 * 3600)
+            );
+            dip_flags.extend(series.dip_check(2., 3.).unwrap());
+        }
+
+        // every value but the last, which has no successor yet, is flagged exactly once
+        assert_eq!(
+            dip_flags,
+            [
+                (Timestamp(0), Flag::DataMissing),
+                (Timestamp(3600), Flag::Pass),
+                (Timestamp(7200), Flag::Fail),
+                (Timestamp(10800), Flag::Pass),
+                (Timestamp(14400), Flag::Pass),
+            ]
+        );
+        assert_eq!(series.dip_check(2., 3.).unwrap(), []);
+        assert_eq!(series.latest_time(), Some(Timestamp(18000)));
+        assert!(series.qc_pending(2, 1, |_| Ok(vec![])).is_err());
+    }
+
+    #[test]
+    fn test_streaming_series_pending() {
+        // pushing several values between runs of the test gives the same flags
+        let mut series =
+            StreamingSeries::new(Timestamp(0), RelativeDuration::hours(1), 10).unwrap();
+        let mut dip_flags = Vec::new();
+        for values in DIP_VALUES.chunks(3) {
+            for value in values {
+                series.push(Some(*value)).unwrap();
+            }
+            dip_flags.extend(series.dip_check(2., 3.).unwrap());
+        }
+        assert_eq!(
+            dip_flags,
+            [
+                (Timestamp(0), Flag::DataMissing),
+                (Timestamp(3600), Flag::Pass),
+                (Timestamp(7200), Flag::Fail),
+                (Timestamp(10800), Flag::Pass),
+                (Timestamp(14400), Flag::Pass),
+            ]
+        );
+
+        // unless the values needed as context have left the buffer
+        let mut series = StreamingSeries::new(Timestamp(0), RelativeDuration::hours(1), 3).unwrap();
+        for value in DIP_VALUES {
+            series.push(Some(value)).unwrap();
+        }
+        assert_eq!(
+            series.dip_check(2., 3.).unwrap(),
+            [
+                (Timestamp(0), Flag::DataMissing),
+                (Timestamp(3600), Flag::DataMissing),
+                (Timestamp(7200), Flag::DataMissing),
+                (Timestamp(10800), Flag::DataMissing),
+                (Timestamp(14400), Flag::Pass),
+            ]
+        );
+
+        // a failed run leaves the values to be QCed by the next
+        let mut series = StreamingSeries::new(Timestamp(0), RelativeDuration::hours(1), 3).unwrap();
+        series.push(Some(1.)).unwrap();
+        series.push(Some(1.)).unwrap();
+        assert!(series.qc_pending(1, 0, |_| Ok(vec![])).is_err());
+        assert_eq!(
+            series.step_check(2., 3.).unwrap(),
+            [
+                (Timestamp(0), Flag::DataMissing),
+                (Timestamp(3600), Flag::Pass)
+            ]
+        );
+    }
+}